@@ -0,0 +1,165 @@
+use crate::decoder::{self, filtered_len};
+use crate::ihdr::Ihdr;
+use crate::image::ImageBuffer;
+use crate::{PngError, Result};
+
+/// One of the seven reduced images of Adam7 interlacing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pass {
+  pub x_start: u32,
+  pub y_start: u32,
+  pub x_step: u32,
+  pub y_step: u32,
+}
+
+pub const PASSES: [Pass; 7] = [
+  Pass { x_start: 0, y_start: 0, x_step: 8, y_step: 8 },
+  Pass { x_start: 4, y_start: 0, x_step: 8, y_step: 8 },
+  Pass { x_start: 0, y_start: 4, x_step: 4, y_step: 8 },
+  Pass { x_start: 2, y_start: 0, x_step: 4, y_step: 4 },
+  Pass { x_start: 0, y_start: 2, x_step: 2, y_step: 4 },
+  Pass { x_start: 1, y_start: 0, x_step: 2, y_step: 2 },
+  Pass { x_start: 0, y_start: 1, x_step: 1, y_step: 2 },
+];
+
+impl Pass {
+  /// Width and height of the reduced image for a full image of the given
+  /// size. Either can be 0 for small images, the pass is then empty.
+  pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+    let reduce = |len: u32, start: u32, step: u32| if len > start { (len - start).div_ceil(step) } else { 0 };
+    (reduce(width, self.x_start, self.x_step), reduce(height, self.y_start, self.y_step))
+  }
+}
+
+/// Size of the filtered image data of all passes together. `None` if it
+/// doesn't fit in memory.
+pub fn filtered_data_len(ihdr: &Ihdr) -> Option<usize> {
+  PASSES.iter().try_fold(0usize, |total, pass| {
+    let (width, height) = pass.size(ihdr.width, ihdr.height);
+    total.checked_add(filtered_len(width, height, ihdr.bits_per_pixel())?)
+  })
+}
+
+/// Decodes the reduced images from inflated image data. The data may be
+/// incomplete, only the passes that are completely there are returned, so
+/// a preview can be shown while the file is still loading.
+pub fn decode_passes(data: &[u8], ihdr: &Ihdr) -> Result<Vec<ImageBuffer>> {
+  let mut passes = Vec::new();
+  let mut offset = 0;
+  for pass in PASSES {
+    let (width, height) = pass.size(ihdr.width, ihdr.height);
+    let len = filtered_len(width, height, ihdr.bits_per_pixel()).ok_or_else(|| PngError::InvalidImageData {
+      reason: format!("image of {}x{} is too large", ihdr.width, ihdr.height),
+    })?;
+    if data.len() - offset < len {
+      break;
+    }
+    passes.push(decoder::decode_scanlines(&data[offset..offset + len], ihdr, width, height)?);
+    offset += len;
+  }
+  Ok(passes)
+}
+
+/// Puts the pixels of the seven reduced images back in their place. Every
+/// pass must have the size and format the header asks for.
+pub fn deinterlace(passes: &[ImageBuffer], ihdr: &Ihdr) -> Result<ImageBuffer> {
+  if passes.len() != PASSES.len() {
+    return Err(PngError::InvalidImageData {
+      reason: format!("adam7 needs 7 passes, got {}", passes.len()),
+    });
+  }
+  for (i, (pass, image)) in PASSES.iter().zip(passes).enumerate() {
+    let (width, height) = pass.size(ihdr.width, ihdr.height);
+    if (image.width(), image.height()) != (width, height)
+      || image.color_type() != ihdr.color_type || image.bit_depth() != ihdr.bit_depth {
+      return Err(PngError::InvalidImageData {
+        reason: format!("pass {} is {}x{} {}-bit {}, the header needs {}x{} {}-bit {}", i + 1,
+          image.width(), image.height(), image.bit_depth(), image.color_type(),
+          width, height, ihdr.bit_depth, ihdr.color_type),
+      });
+    }
+  }
+
+  let bytes_per_pixel = ihdr.color_type.channels() as usize * if ihdr.bit_depth == 16 { 2 } else { 1 };
+  let row_len = ihdr.width as usize * bytes_per_pixel;
+  let mut data = vec![0; row_len * ihdr.height as usize];
+  for (pass, image) in PASSES.iter().zip(passes) {
+    for y in 0..image.height() {
+      let full_y = (pass.y_start + y * pass.y_step) as usize;
+      for x in 0..image.width() {
+        let full_x = (pass.x_start + x * pass.x_step) as usize;
+        let start = full_y * row_len + full_x * bytes_per_pixel;
+        data[start..start + bytes_per_pixel].copy_from_slice(image.pixel(x, y));
+      }
+    }
+  }
+  ImageBuffer::new(ihdr.width, ihdr.height, ihdr.color_type, ihdr.bit_depth, data)
+}
+
+/// Splits an image into the seven reduced images, in pass order.
+pub fn interlace(image: &ImageBuffer) -> Vec<ImageBuffer> {
+  let bytes_per_pixel = image.bytes_per_pixel();
+  PASSES.iter().map(|pass| {
+    let (width, height) = pass.size(image.width(), image.height());
+    let mut data = Vec::with_capacity(width as usize * height as usize * bytes_per_pixel);
+    for y in 0..height {
+      for x in 0..width {
+        data.extend_from_slice(image.pixel(pass.x_start + x * pass.x_step, pass.y_start + y * pass.y_step));
+      }
+    }
+    // same layout as the full image, only smaller, so this can't fail
+    ImageBuffer::new(width, height, image.color_type(), image.bit_depth(), data).unwrap()
+  }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ihdr::ColorType;
+
+  #[test]
+  fn test_pass_sizes() {
+    let sizes: Vec<(u32, u32)> = PASSES.iter().map(|p| p.size(8, 8)).collect();
+    assert_eq!(sizes, vec![(1, 1), (1, 1), (2, 1), (2, 2), (4, 2), (4, 4), (8, 4)]);
+
+    let sizes: Vec<(u32, u32)> = PASSES.iter().map(|p| p.size(1, 1)).collect();
+    assert_eq!(sizes, vec![(1, 1), (0, 1), (1, 0), (0, 1), (1, 0), (0, 1), (1, 0)]);
+  }
+
+  #[test]
+  fn test_interlace_round_trip() {
+    let data: Vec<u8> = (0..11 * 5).map(|i| i as u8).collect();
+    let image = ImageBuffer::new(11, 5, ColorType::Grayscale, 8, data).unwrap();
+    let passes = interlace(&image);
+    // the first pass holds every 8th pixel of every 8th row
+    assert_eq!(passes[0].data(), &[0, 8]);
+
+    let mut ihdr = Ihdr::new(11, 5, 8, ColorType::Grayscale).unwrap();
+    ihdr.interlace_method = 1;
+    assert_eq!(deinterlace(&passes, &ihdr).unwrap(), image);
+    assert!(deinterlace(&passes[..6], &ihdr).is_err());
+  }
+
+  #[test]
+  fn test_deinterlace_mismatched_passes() {
+    let mut ihdr = Ihdr::new(2, 2, 8, ColorType::Grayscale).unwrap();
+    ihdr.interlace_method = 1;
+    let rgba = ImageBuffer::new(8, 8, ColorType::Rgba, 8, vec![0; 8 * 8 * 4]).unwrap();
+    assert!(matches!(deinterlace(&vec![rgba; 7], &ihdr), Err(PngError::InvalidImageData { .. })));
+
+    // right size, wrong format
+    let image = ImageBuffer::new(2, 2, ColorType::Grayscale, 16, vec![0; 8]).unwrap();
+    assert!(deinterlace(&interlace(&image), &ihdr).is_err());
+    // right format, wrong size
+    let image = ImageBuffer::new(3, 2, ColorType::Grayscale, 8, vec![0; 6]).unwrap();
+    assert!(deinterlace(&interlace(&image), &ihdr).is_err());
+  }
+
+  #[test]
+  fn test_filtered_data_len() {
+    let mut ihdr = Ihdr::new(8, 8, 8, ColorType::Rgb).unwrap();
+    ihdr.interlace_method = 1;
+    // every pass row has a filter byte, 15 rows, 64 pixels of 3 bytes
+    assert_eq!(filtered_data_len(&ihdr), Some(15 + 64 * 3));
+  }
+}
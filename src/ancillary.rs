@@ -0,0 +1,546 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use crate::chunk::{Chunk, array4_from_slice, check_type, invalid};
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::palette::Palette;
+use crate::{Error, Result};
+
+/// Scale of the fixed point numbers in gAMA and cHRM.
+const FIXED_POINT_SCALE: f64 = 100_000.0;
+
+/// The gAMA chunk, the gamma times 100000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gamma(pub u32);
+
+impl Gamma {
+  pub fn value(&self) -> f64 {
+    self.0 as f64 / FIXED_POINT_SCALE
+  }
+
+  pub fn to_chunk(&self) -> Chunk {
+    new_chunk("gAMA", self.0.to_be_bytes().to_vec())
+  }
+}
+
+impl TryFrom<&Chunk> for Gamma {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    let data = check_chunk(chunk, "gAMA", 4)?;
+    let gamma = Gamma(read_u32(data, 0));
+    if gamma.0 == 0 {
+      return Err(invalid("gAMA", "gamma is 0"));
+    }
+    Ok(gamma)
+  }
+}
+
+impl Display for Gamma {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "gAMA: {:.5}", self.value())
+  }
+}
+
+/// The cHRM chunk, CIE x and y of the white point and the primaries, each
+/// times 100000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticities {
+  pub white: (u32, u32),
+  pub red: (u32, u32),
+  pub green: (u32, u32),
+  pub blue: (u32, u32),
+}
+
+impl Chromaticities {
+  pub fn to_chunk(&self) -> Chunk {
+    let data = [self.white, self.red, self.green, self.blue].iter()
+      .flat_map(|&(x, y)| [x.to_be_bytes(), y.to_be_bytes()])
+      .flatten()
+      .collect();
+    new_chunk("cHRM", data)
+  }
+}
+
+impl TryFrom<&Chunk> for Chromaticities {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    let data = check_chunk(chunk, "cHRM", 32)?;
+    let point = |i: usize| (read_u32(data, i * 8), read_u32(data, i * 8 + 4));
+    Ok(Chromaticities { white: point(0), red: point(1), green: point(2), blue: point(3) })
+  }
+}
+
+impl Display for Chromaticities {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let point = |(x, y): (u32, u32)| format!("({:.5}, {:.5})", x as f64 / FIXED_POINT_SCALE, y as f64 / FIXED_POINT_SCALE);
+    write!(f, "cHRM: white {} red {} green {} blue {}",
+      point(self.white), point(self.red), point(self.green), point(self.blue))
+  }
+}
+
+/// The sRGB chunk, the image is in the sRGB color space and should be
+/// rendered with this intent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+  Perceptual = 0,
+  RelativeColorimetric = 1,
+  Saturation = 2,
+  AbsoluteColorimetric = 3,
+}
+
+impl RenderingIntent {
+  pub fn to_chunk(&self) -> Chunk {
+    new_chunk("sRGB", vec![*self as u8])
+  }
+}
+
+impl TryFrom<&Chunk> for RenderingIntent {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    let data = check_chunk(chunk, "sRGB", 1)?;
+    match data[0] {
+      0 => Ok(RenderingIntent::Perceptual),
+      1 => Ok(RenderingIntent::RelativeColorimetric),
+      2 => Ok(RenderingIntent::Saturation),
+      3 => Ok(RenderingIntent::AbsoluteColorimetric),
+      intent => Err(invalid("sRGB", format!("unknown rendering intent {}", intent))),
+    }
+  }
+}
+
+impl Display for RenderingIntent {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      RenderingIntent::Perceptual => "perceptual",
+      RenderingIntent::RelativeColorimetric => "relative colorimetric",
+      RenderingIntent::Saturation => "saturation",
+      RenderingIntent::AbsoluteColorimetric => "absolute colorimetric",
+    };
+    write!(f, "sRGB: {}", name)
+  }
+}
+
+/// The pHYs chunk, pixels per unit in both directions. Without a unit only
+/// the aspect ratio is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+  pub x: u32,
+  pub y: u32,
+  pub in_meters: bool,
+}
+
+impl PhysicalDimensions {
+  /// Converts pixels per meter to dots per inch, `None` without a unit.
+  pub fn dpi(&self) -> Option<(u32, u32)> {
+    let dpi = |ppm: u32| (ppm as f64 * 0.0254).round() as u32;
+    self.in_meters.then(|| (dpi(self.x), dpi(self.y)))
+  }
+
+  pub fn to_chunk(&self) -> Chunk {
+    let data = [&self.x.to_be_bytes()[..], &self.y.to_be_bytes(), &[u8::from(self.in_meters)]].concat();
+    new_chunk("pHYs", data)
+  }
+}
+
+impl TryFrom<&Chunk> for PhysicalDimensions {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    let data = check_chunk(chunk, "pHYs", 9)?;
+    if data[8] > 1 {
+      return Err(invalid("pHYs", format!("unknown unit {}", data[8])));
+    }
+    Ok(PhysicalDimensions { x: read_u32(data, 0), y: read_u32(data, 4), in_meters: data[8] == 1 })
+  }
+}
+
+impl Display for PhysicalDimensions {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self.dpi() {
+      Some((x, y)) if x == y => write!(f, "pHYs: {}x{} px/m ({} DPI)", self.x, self.y, x),
+      Some((x, y)) => write!(f, "pHYs: {}x{} px/m ({}x{} DPI)", self.x, self.y, x, y),
+      None => write!(f, "pHYs: {}x{} (aspect ratio only)", self.x, self.y),
+    }
+  }
+}
+
+/// The tIME chunk, when the image was last changed, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LastModified {
+  pub year: u16,
+  pub month: u8,
+  pub day: u8,
+  pub hour: u8,
+  pub minute: u8,
+  /// Up to 60 for leap seconds.
+  pub second: u8,
+}
+
+impl LastModified {
+  /// Checks that every field is in range.
+  pub fn validate(&self) -> Result<()> {
+    let fields = [
+      ("month", self.month, 1, 12), ("day", self.day, 1, 31), ("hour", self.hour, 0, 23),
+      ("minute", self.minute, 0, 59), ("second", self.second, 0, 60),
+    ];
+    for (name, value, min, max) in fields {
+      if value < min || value > max {
+        return Err(invalid("tIME", format!("{} is {}, must be {} to {}", name, value, min, max)));
+      }
+    }
+    Ok(())
+  }
+
+  pub fn to_chunk(&self) -> Chunk {
+    let data = [&self.year.to_be_bytes()[..], &[self.month, self.day, self.hour, self.minute, self.second]].concat();
+    new_chunk("tIME", data)
+  }
+}
+
+impl TryFrom<&Chunk> for LastModified {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    let data = check_chunk(chunk, "tIME", 7)?;
+    let time = LastModified {
+      year: u16::from_be_bytes([data[0], data[1]]),
+      month: data[2],
+      day: data[3],
+      hour: data[4],
+      minute: data[5],
+      second: data[6],
+    };
+    time.validate()?;
+    Ok(time)
+  }
+}
+
+impl Display for LastModified {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "tIME: {:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+      self.year, self.month, self.day, self.hour, self.minute, self.second)
+  }
+}
+
+/// The bKGD chunk, a background color to show the image on. Its layout
+/// depends on the color type, so it is parsed together with the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+  Indexed(u8),
+  Gray(u16),
+  Rgb(u16, u16, u16),
+}
+
+impl Background {
+  pub fn from_chunk(chunk: &Chunk, ihdr: &Ihdr) -> Result<Background> {
+    let len = match ihdr.color_type {
+      ColorType::Indexed => 1,
+      ColorType::Grayscale | ColorType::GrayscaleAlpha => 2,
+      ColorType::Rgb | ColorType::Rgba => 6,
+    };
+    let data = check_chunk(chunk, "bKGD", len)?;
+    let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+    let background = match ihdr.color_type {
+      ColorType::Indexed => Background::Indexed(data[0]),
+      ColorType::Grayscale | ColorType::GrayscaleAlpha => Background::Gray(sample(0)),
+      ColorType::Rgb | ColorType::Rgba => Background::Rgb(sample(0), sample(2), sample(4)),
+    };
+    background.validate(ihdr, None)?;
+    Ok(background)
+  }
+
+  /// Checks the color against the header and, for indexed images, that the
+  /// index is in the palette.
+  pub fn validate(&self, ihdr: &Ihdr, palette: Option<&Palette>) -> Result<()> {
+    let max = if ihdr.bit_depth == 16 { u16::MAX } else { (1u16 << ihdr.bit_depth) - 1 };
+    let fits = |samples: &[u16]| match samples.iter().find(|&&s| s > max) {
+      Some(sample) => Err(invalid("bKGD", format!("sample {} doesn't fit in {} bits", sample, ihdr.bit_depth))),
+      None => Ok(()),
+    };
+    match (self, ihdr.color_type) {
+      (Background::Indexed(index), ColorType::Indexed) => match palette {
+        Some(palette) if *index as usize >= palette.entries.len() =>
+          Err(invalid("bKGD", format!("index {} is not in the palette of {} entries", index, palette.entries.len()))),
+        _ => Ok(()),
+      },
+      (Background::Gray(gray), ColorType::Grayscale | ColorType::GrayscaleAlpha) => fits(&[*gray]),
+      (Background::Rgb(r, g, b), ColorType::Rgb | ColorType::Rgba) => fits(&[*r, *g, *b]),
+      _ => Err(invalid("bKGD", format!("doesn't match color type {}", ihdr.color_type))),
+    }
+  }
+
+  pub fn to_chunk(&self) -> Chunk {
+    let data = match self {
+      Background::Indexed(index) => vec![*index],
+      Background::Gray(gray) => gray.to_be_bytes().to_vec(),
+      Background::Rgb(r, g, b) => [r.to_be_bytes(), g.to_be_bytes(), b.to_be_bytes()].concat(),
+    };
+    new_chunk("bKGD", data)
+  }
+}
+
+impl Display for Background {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Background::Indexed(index) => write!(f, "bKGD: palette index {}", index),
+      Background::Gray(gray) => write!(f, "bKGD: gray {}", gray),
+      Background::Rgb(r, g, b) => write!(f, "bKGD: rgb ({}, {}, {})", r, g, b),
+    }
+  }
+}
+
+/// The sBIT chunk, the number of significant bits of each channel in the
+/// original image. Indexed images list red, green and blue of the palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignificantBits(pub Vec<u8>);
+
+impl SignificantBits {
+  pub fn from_chunk(chunk: &Chunk, ihdr: &Ihdr) -> Result<SignificantBits> {
+    let data = check_chunk(chunk, "sBIT", SignificantBits::channels(ihdr))?;
+    let bits = SignificantBits(data.to_vec());
+    bits.validate(ihdr)?;
+    Ok(bits)
+  }
+
+  fn channels(ihdr: &Ihdr) -> usize {
+    match ihdr.color_type {
+      ColorType::Indexed => 3,
+      color_type => color_type.channels() as usize,
+    }
+  }
+
+  /// Checks that there is one value per channel, each between 1 and the
+  /// sample depth.
+  pub fn validate(&self, ihdr: &Ihdr) -> Result<()> {
+    let expected = SignificantBits::channels(ihdr);
+    if self.0.len() != expected {
+      return Err(invalid("sBIT", format!("has {} values, color type {} needs {}", self.0.len(), ihdr.color_type, expected)));
+    }
+    let depth = if ihdr.color_type == ColorType::Indexed { 8 } else { ihdr.bit_depth };
+    if let Some(bits) = self.0.iter().find(|&&b| b == 0 || b > depth) {
+      return Err(invalid("sBIT", format!("{} significant bits, must be 1 to {}", bits, depth)));
+    }
+    Ok(())
+  }
+
+  pub fn to_chunk(&self) -> Chunk {
+    new_chunk("sBIT", self.0.clone())
+  }
+}
+
+impl Display for SignificantBits {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let bits: Vec<String> = self.0.iter().map(|b| b.to_string()).collect();
+    write!(f, "sBIT: {} bits", bits.join(", "))
+  }
+}
+
+/// The hIST chunk, how often each palette entry is used, scaled to fit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram(pub Vec<u16>);
+
+impl Histogram {
+  /// Checks that there is one frequency per palette entry.
+  pub fn validate(&self, palette: &Palette) -> Result<()> {
+    if self.0.len() != palette.entries.len() {
+      return Err(invalid("hIST", format!("has {} entries, the palette {}", self.0.len(), palette.entries.len())));
+    }
+    Ok(())
+  }
+
+  pub fn to_chunk(&self) -> Chunk {
+    new_chunk("hIST", self.0.iter().flat_map(|f| f.to_be_bytes()).collect())
+  }
+}
+
+impl TryFrom<&Chunk> for Histogram {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    // the length depends on the palette, see `validate`
+    check_type(chunk, "hIST")?;
+    let data = chunk.data();
+    if !data.len().is_multiple_of(2) {
+      return Err(invalid("hIST", format!("length {} is not a multiple of 2", data.len())));
+    }
+    Ok(Histogram(data.chunks_exact(2).map(|f| u16::from_be_bytes([f[0], f[1]])).collect()))
+  }
+}
+
+impl Display for Histogram {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let max = self.0.iter().max().copied().unwrap_or(0);
+    write!(f, "hIST: {} entries, highest frequency {}", self.0.len(), max)
+  }
+}
+
+/// Any of the ancillary chunks above, so they can be handled in one go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ancillary {
+  Gamma(Gamma),
+  Chromaticities(Chromaticities),
+  StandardRgb(RenderingIntent),
+  PhysicalDimensions(PhysicalDimensions),
+  LastModified(LastModified),
+  Background(Background),
+  SignificantBits(SignificantBits),
+  Histogram(Histogram),
+}
+
+impl Ancillary {
+  /// Parses the chunk if it is one of the known ancillary chunks and
+  /// validates it against the header and palette. `None` for other chunks.
+  /// hIST can only be checked with a palette.
+  pub fn from_chunk(chunk: &Chunk, ihdr: &Ihdr, palette: Option<&Palette>) -> Result<Option<Ancillary>> {
+    let ancillary = match &chunk.chunk_type().bytes() {
+      b"gAMA" => Ancillary::Gamma(Gamma::try_from(chunk)?),
+      b"cHRM" => Ancillary::Chromaticities(Chromaticities::try_from(chunk)?),
+      b"sRGB" => Ancillary::StandardRgb(RenderingIntent::try_from(chunk)?),
+      b"pHYs" => Ancillary::PhysicalDimensions(PhysicalDimensions::try_from(chunk)?),
+      b"tIME" => Ancillary::LastModified(LastModified::try_from(chunk)?),
+      b"bKGD" => {
+        let background = Background::from_chunk(chunk, ihdr)?;
+        background.validate(ihdr, palette)?;
+        Ancillary::Background(background)
+      },
+      b"sBIT" => Ancillary::SignificantBits(SignificantBits::from_chunk(chunk, ihdr)?),
+      b"hIST" => {
+        let histogram = Histogram::try_from(chunk)?;
+        let palette = palette.ok_or_else(|| invalid("hIST", "the image has no palette"))?;
+        histogram.validate(palette)?;
+        Ancillary::Histogram(histogram)
+      },
+      _ => return Ok(None),
+    };
+    Ok(Some(ancillary))
+  }
+
+  pub fn to_chunk(&self) -> Chunk {
+    match self {
+      Ancillary::Gamma(a) => a.to_chunk(),
+      Ancillary::Chromaticities(a) => a.to_chunk(),
+      Ancillary::StandardRgb(a) => a.to_chunk(),
+      Ancillary::PhysicalDimensions(a) => a.to_chunk(),
+      Ancillary::LastModified(a) => a.to_chunk(),
+      Ancillary::Background(a) => a.to_chunk(),
+      Ancillary::SignificantBits(a) => a.to_chunk(),
+      Ancillary::Histogram(a) => a.to_chunk(),
+    }
+  }
+}
+
+impl Display for Ancillary {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Ancillary::Gamma(a) => a.fmt(f),
+      Ancillary::Chromaticities(a) => a.fmt(f),
+      Ancillary::StandardRgb(a) => a.fmt(f),
+      Ancillary::PhysicalDimensions(a) => a.fmt(f),
+      Ancillary::LastModified(a) => a.fmt(f),
+      Ancillary::Background(a) => a.fmt(f),
+      Ancillary::SignificantBits(a) => a.fmt(f),
+      Ancillary::Histogram(a) => a.fmt(f),
+    }
+  }
+}
+
+/// Checks the type and length of the chunk and returns its data.
+fn check_chunk<'a>(chunk: &'a Chunk, expected: &str, len: usize) -> Result<&'a [u8]> {
+  check_type(chunk, expected)?;
+  if chunk.data().len() != len {
+    return Err(invalid(expected, format!("length is {}, must be {}", chunk.data().len(), len)));
+  }
+  Ok(chunk.data())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+  u32::from_be_bytes(array4_from_slice(&data[offset..offset + 4]))
+}
+
+fn new_chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+  Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::png::Png;
+  use crate::png::tests::PNG_FILE;
+
+  #[test]
+  fn test_image_file_chunks() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    let ihdr = png.header_info().unwrap();
+    let rendered: Vec<String> = png.chunks().iter()
+      .filter_map(|c| Ancillary::from_chunk(c, &ihdr, None).unwrap())
+      .map(|a| a.to_string())
+      .collect();
+    assert_eq!(rendered, vec!["sRGB: perceptual", "gAMA: 0.45455", "pHYs: 3778x3778 px/m (96 DPI)"]);
+
+    // serializing gives back the same bytes
+    for chunk in png.chunks() {
+      if let Some(ancillary) = Ancillary::from_chunk(chunk, &ihdr, None).unwrap() {
+        assert_eq!(ancillary.to_chunk().as_bytes(), chunk.as_bytes());
+      }
+    }
+  }
+
+  #[test]
+  fn test_round_trips() {
+    let rgb = Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap();
+    let palette = Palette { entries: vec![[0; 3]; 3] };
+    let values = [
+      Ancillary::Chromaticities(Chromaticities {
+        white: (31270, 32900), red: (64000, 33000), green: (30000, 60000), blue: (15000, 6000),
+      }),
+      Ancillary::LastModified(LastModified { year: 2024, month: 2, day: 29, hour: 23, minute: 59, second: 60 }),
+      Ancillary::Background(Background::Rgb(255, 0, 128)),
+      Ancillary::SignificantBits(SignificantBits(vec![5, 6, 5])),
+      Ancillary::Histogram(Histogram(vec![1, 0, 300])),
+      Ancillary::PhysicalDimensions(PhysicalDimensions { x: 1, y: 2, in_meters: false }),
+    ];
+    for value in values {
+      assert_eq!(Ancillary::from_chunk(&value.to_chunk(), &rgb, Some(&palette)).unwrap(), Some(value));
+    }
+  }
+
+  #[test]
+  fn test_display() {
+    assert_eq!(PhysicalDimensions { x: 2835, y: 2835, in_meters: true }.to_string(), "pHYs: 2835x2835 px/m (72 DPI)");
+    assert_eq!(PhysicalDimensions { x: 2835, y: 3780, in_meters: true }.to_string(), "pHYs: 2835x3780 px/m (72x96 DPI)");
+    let time = LastModified { year: 2001, month: 9, day: 9, hour: 1, minute: 46, second: 40 };
+    assert_eq!(time.to_string(), "tIME: 2001-09-09 01:46:40 UTC");
+    assert_eq!(SignificantBits(vec![5, 6, 5]).to_string(), "sBIT: 5, 6, 5 bits");
+  }
+
+  #[test]
+  fn test_validation() {
+    let gray = Ihdr::new(1, 1, 4, ColorType::Grayscale).unwrap();
+    let indexed = Ihdr::new(1, 1, 2, ColorType::Indexed).unwrap();
+    let palette = Palette { entries: vec![[0; 3]; 2] };
+
+    assert!(Gamma::try_from(&Gamma(0).to_chunk()).is_err());
+    assert!(RenderingIntent::try_from(&new_chunk("sRGB", vec![4])).is_err());
+    assert!(PhysicalDimensions::try_from(&new_chunk("pHYs", vec![0; 8])).is_err());
+    assert!(LastModified::try_from(&new_chunk("tIME", vec![7, 208, 13, 1, 0, 0, 0])).is_err());
+
+    assert!(Background::from_chunk(&Background::Gray(15).to_chunk(), &gray).is_ok());
+    assert!(Background::from_chunk(&Background::Gray(16).to_chunk(), &gray).is_err());
+    assert!(Background::from_chunk(&Background::Rgb(0, 0, 0).to_chunk(), &gray).is_err());
+    assert!(Background::Indexed(2).validate(&indexed, Some(&palette)).is_err());
+
+    assert!(SignificantBits::from_chunk(&new_chunk("sBIT", vec![4]), &gray).is_ok());
+    assert!(SignificantBits::from_chunk(&new_chunk("sBIT", vec![5]), &gray).is_err());
+    assert!(SignificantBits::from_chunk(&new_chunk("sBIT", vec![8, 8, 8]), &indexed).is_ok());
+    assert!(SignificantBits::from_chunk(&new_chunk("sBIT", vec![8]), &indexed).is_err());
+
+    let histogram = Histogram(vec![1, 2, 3]);
+    assert!(histogram.validate(&palette).is_err());
+    assert!(Ancillary::from_chunk(&histogram.to_chunk(), &indexed, None).is_err());
+    assert!(Histogram::try_from(&new_chunk("hIST", vec![0; 3])).is_err());
+  }
+}
@@ -1,16 +1,87 @@
+use std::ffi::OsString;
 use std::fmt::Display;
-use std::path::PathBuf;
-use clap::{AppSettings, Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use clap::{AppSettings, ArgEnum, Parser, Subcommand};
+use glob::Pattern;
 
-use crate::chunk_type::ChunkType;
+use pngproject::chunk_type::ChunkType;
+use pngproject::position::Position;
+use pngproject::split;
+use pngproject::{PngError, Result};
+
+/// What encode, decode and remove work on: a chunk type or a text keyword.
+/// Remove takes several chunk types instead of one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+  Chunk(ChunkType),
+  Chunks(ChunkFilter),
+  Keyword { keyword: String, compress: bool, language: Option<String> },
+}
+
+/// Chunk types or glob patterns like `t???`, optionally only ancillary
+/// chunks. A lone `*` matches all private chunks, no patterns match every
+/// chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkFilter {
+  pub patterns: Vec<Pattern>,
+  pub ancillary_only: bool,
+}
+
+impl ChunkFilter {
+  pub fn matches(&self, chunk_type: &ChunkType) -> bool {
+    let name = chunk_type.to_string();
+    let matches = |pattern: &Pattern| match pattern.as_str() {
+      "*" => !chunk_type.is_public(),
+      _ => pattern.matches(&name),
+    };
+    (self.patterns.is_empty() || self.patterns.iter().any(matches))
+      && !(self.ancillary_only && chunk_type.is_critical())
+  }
+}
+
+impl Display for ChunkFilter {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let patterns: Vec<&str> = self.patterns.iter().map(Pattern::as_str).collect();
+    write!(f, "{}", if patterns.is_empty() { "*".to_string() } else { patterns.join(", ") })?;
+    if self.ancillary_only {
+      write!(f, " (ancillary only)")?;
+    }
+    Ok(())
+  }
+}
+
+impl Target {
+  fn new(chunk_type: Option<ChunkType>, keyword: Option<String>, compress: bool, language: Option<String>) -> Target {
+    match (chunk_type, keyword) {
+      (_, Some(keyword)) => Target::Keyword { keyword, compress, language },
+      // clap makes sure one of them is given
+      (Some(chunk_type), None) => Target::Chunk(chunk_type),
+      (None, None) => unreachable!(),
+    }
+  }
+}
+
+impl Display for Target {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Target::Chunk(chunk_type) => write!(f, "{}", chunk_type),
+      Target::Chunks(filter) => write!(f, "chunks matching {}", filter),
+      Target::Keyword {keyword, ..} => write!(f, "{:?}", keyword),
+    }
+  }
+}
 
 impl Display for Cmd {
   fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
     match self {
-      Cmd::Encode {file, chunk_type, message, output} => write!(f, "encode {:?} {}", output, chunk_type),
-      Cmd::Decode {file, chunk_type} => write!(f, "decode"),
-      Cmd::Remove {file, chunk_type} => write!(f, "remove"),
-      Cmd::Print {file} => write!(f, "print")
+      Cmd::Encode {output, keyword: Some(keyword), ..} => write!(f, "encode {:?} {:?}", output, keyword),
+      Cmd::Encode {chunk_type, output, ..} => write!(f, "encode {:?} {}", output, chunk_type.as_deref().unwrap_or_default()),
+      Cmd::Decode {..} => write!(f, "decode"),
+      Cmd::Remove {..} => write!(f, "remove"),
+      Cmd::Update {..} => write!(f, "update"),
+      Cmd::Print {..} => write!(f, "print"),
+      Cmd::Validate {..} => write!(f, "validate"),
     }
   }
 }
@@ -22,6 +93,27 @@ impl Display for Cmd {
 pub struct Args {
 #[clap(subcommand)]
   pub command: Option<Cmd>,
+
+  /// Output for people or for scripts, errors go to stderr in the same format
+  #[clap(long, global = true, arg_enum, default_value = "text")]
+  pub format: Format,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+  Text,
+  Json,
+}
+
+impl Format {
+  /// The format asked for on the command line, for reporting errors in
+  /// arguments that can't be parsed.
+  pub fn requested() -> Format {
+    let args: Vec<String> = std::env::args().collect();
+    let json = args.windows(2).any(|pair| pair[0] == "--format" && pair[1] == "json")
+      || args.iter().any(|arg| arg == "--format=json");
+    if json { Format::Json } else { Format::Text }
+  }
 }
 
 #[derive(Subcommand, Debug)]
@@ -32,14 +124,44 @@ pub enum Cmd {
     #[clap(parse(from_os_str), value_name = "FILE")]
     file: PathBuf,
 
-    #[clap(parse(try_from_str), value_name="CHUNK_TYPE")]
-    chunk_type: ChunkType,
+    /// Left out with --keyword, the message comes first then
+    #[clap(value_name="CHUNK_TYPE", required_unless_present = "keyword")]
+    chunk_type: Option<String>,
 
-    #[clap(value_name = "MESSAGE", default_value="")]
-    message: String,
+    /// Left out with --input-file or --stdin, OUTPUT comes first then
+    #[clap(value_name = "MESSAGE")]
+    message: Option<String>,
 
     #[clap(parse(from_os_str), value_name = "OUTPUT")]
     output: Option<PathBuf>,
+
+    /// Store the message as an iTXt text with this keyword
+    #[clap(long, value_name = "KEY")]
+    keyword: Option<String>,
+
+    /// Compress the iTXt text
+    #[clap(long, requires = "keyword")]
+    compress: bool,
+
+    /// Language tag of the iTXt text, e.g. "en"
+    #[clap(long, value_name = "TAG", requires = "keyword")]
+    language: Option<String>,
+
+    /// Where the chunk goes: before-iend, after-ihdr, before-idat,
+    /// after-idat, an index, before:TYPE or after:TYPE
+    #[clap(long, value_name = "POS", default_value = "before-iend", conflicts_with = "keyword")]
+    position: Position,
+
+    /// Larger messages are split over several chunks of at most this many
+    /// bytes, decode joins them again
+    #[clap(long, value_name = "BYTES", default_value_t = split::DEFAULT_MAX_LEN, conflicts_with = "keyword")]
+    max_chunk_size: usize,
+
+    #[clap(flatten)]
+    input: InputArgs,
+
+    #[clap(flatten)]
+    write: WriteArgs,
   },
   /// Decode a png file
   #[clap(setting(AppSettings::ArgRequiredElseHelp))]
@@ -47,24 +169,295 @@ pub enum Cmd {
     #[clap(parse(from_os_str), value_name = "FILE")]
     file: PathBuf,
 
-    #[clap(parse(try_from_str), value_name="CHUNK_TYPE")]
-    chunk_type: ChunkType,
+    #[clap(parse(try_from_str), value_name="CHUNK_TYPE", required_unless_present = "keyword")]
+    chunk_type: Option<ChunkType>,
+
+    /// Use the iTXt, tEXt or zTXt text with this keyword
+    #[clap(long, value_name = "KEY", conflicts_with = "chunk-type")]
+    keyword: Option<String>,
+
+    #[clap(flatten)]
+    output: OutputArgs,
   },
   
-  /// Remove a chunk from a png file
+  /// Remove chunks from a png file
   #[clap(setting(AppSettings::ArgRequiredElseHelp))]
   Remove {
     #[clap(parse(from_os_str), value_name = "FILE")]
     file: PathBuf,
 
-    #[clap(parse(try_from_str), value_name="CHUNK_TYPE")]
+    /// Chunk types or glob patterns, e.g. "tEXt", "t???" or "*" for all
+    /// private chunks
+    #[clap(parse(try_from_str = Pattern::new), value_name = "CHUNK_TYPE",
+      required_unless_present_any = &["keyword", "ancillary-only"])]
+    chunk_types: Vec<Pattern>,
+
+    /// Use the iTXt, tEXt or zTXt text with this keyword
+    #[clap(long, value_name = "KEY", conflicts_with_all = &["chunk-types", "ancillary-only"])]
+    keyword: Option<String>,
+
+    /// Only remove ancillary chunks, all of them without CHUNK_TYPE
+    #[clap(long)]
+    ancillary_only: bool,
+
+    /// Also remove PLTE and IDAT, the result is written even if it breaks
+    /// the structure rules. IHDR and IEND are never removed
+    #[clap(long)]
+    force: bool,
+
+    /// Write the result to this file
+    #[clap(short, long, parse(from_os_str), value_name = "OUTPUT", conflicts_with = "in-place")]
+    output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    write: WriteArgs,
+  },
+  /// Replace the data of a chunk, keeping its position
+  #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+  Update {
+    #[clap(parse(from_os_str), value_name = "FILE")]
+    file: PathBuf,
+
+    #[clap(parse(try_from_str), value_name = "CHUNK_TYPE")]
     chunk_type: ChunkType,
+
+    #[clap(value_name = "MESSAGE")]
+    message: String,
+
+    /// Which chunk of CHUNK_TYPE to update when there are several, from 0
+    #[clap(long, value_name = "N", default_value = "0")]
+    index: usize,
+
+    /// Write the result to this file
+    #[clap(short, long, parse(from_os_str), value_name = "OUTPUT", conflicts_with = "in-place")]
+    output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    write: WriteArgs,
   },
   /// Print a png file
   #[clap(setting(AppSettings::ArgRequiredElseHelp))]
   Print {
     #[clap(parse(from_os_str), value_name = "FILE")]
     file: PathBuf,
+
+    /// Show all chunk data instead of a preview
+    #[clap(long)]
+    full: bool,
+
+    /// Show a hex dump of the data of every chunk
+    #[clap(long)]
+    hex: bool,
+  },
+  /// Check png files against the spec, exits with 1 if any has problems
+  #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+  Validate {
+    #[clap(parse(from_os_str), value_name = "FILES", required = true)]
+    files: Vec<PathBuf>,
+  },
+}
+
+
+/// Where encode takes the message from and how it is encoded.
+#[derive(clap::Args, Debug)]
+pub struct InputArgs {
+  /// Read the message from this file instead of MESSAGE
+  #[clap(long, parse(from_os_str), value_name = "PATH", conflicts_with = "stdin")]
+  pub input_file: Option<PathBuf>,
+
+  /// Read the message from stdin instead of MESSAGE
+  #[clap(long)]
+  pub stdin: bool,
+
+  /// The message is hex, e.g. "89504e47"
+  #[clap(long, conflicts_with = "base64")]
+  pub hex: bool,
+
+  /// The message is base64
+  #[clap(long)]
+  pub base64: bool,
+
+  /// Encrypt the message with a password, taken from $PNGPROJECT_PASSWORD
+  /// or asked for
+  #[clap(long, conflicts_with = "keyword")]
+  pub encrypt: bool,
+}
+
+impl InputArgs {
+  /// Whether the message comes from somewhere else than MESSAGE.
+  pub fn replaces_message(&self) -> bool {
+    self.input_file.is_some() || self.stdin
+  }
+
+  pub fn encoding(&self) -> Encoding {
+    match (self.hex, self.base64) {
+      (true, _) => Encoding::Hex,
+      (_, true) => Encoding::Base64,
+      _ => Encoding::Raw,
+    }
+  }
+}
+
+/// Where decode writes the data to and how it is encoded.
+#[derive(clap::Args, Debug)]
+pub struct OutputArgs {
+  /// Write the data to this file instead of stdout
+  #[clap(long, parse(from_os_str), value_name = "PATH")]
+  pub output_file: Option<PathBuf>,
+
+  /// Write the data as it is, also when it is not text
+  #[clap(long, conflicts_with_all = &["hex", "base64"])]
+  pub raw: bool,
+
+  /// Write the data as hex
+  #[clap(long, conflicts_with = "base64")]
+  pub hex: bool,
+
+  /// Write the data as base64
+  #[clap(long)]
+  pub base64: bool,
+
+  /// Decrypt data stored with encode --encrypt, the password is taken from
+  /// $PNGPROJECT_PASSWORD or asked for
+  #[clap(long, conflicts_with = "keyword")]
+  pub decrypt: bool,
+}
+
+impl OutputArgs {
+  /// Text unless asked otherwise, a file gets the data as it is.
+  pub fn encoding(&self) -> Encoding {
+    match (self.hex, self.base64, self.raw || self.output_file.is_some()) {
+      (true, _, _) => Encoding::Hex,
+      (_, true, _) => Encoding::Base64,
+      (_, _, true) => Encoding::Raw,
+      _ => Encoding::Text,
+    }
+  }
+}
+
+/// How chunk data is written on the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+  /// utf8 text, anything else is an error
+  Text,
+  /// the bytes as they are
+  Raw,
+  Hex,
+  Base64,
+}
+
+/// Where and how edited files are written.
+#[derive(clap::Args, Debug)]
+pub struct WriteArgs {
+  /// Overwrite FILE instead of writing to OUTPUT
+  #[clap(long)]
+  pub in_place: bool,
+
+  /// Keep a copy of the file that gets replaced, named with this suffix
+  #[clap(long, value_name = "SUFFIX", forbid_empty_values = true)]
+  pub backup: Option<String>,
+
+  /// Keep the permissions and timestamps of FILE
+  #[clap(long)]
+  pub preserve: bool,
+}
+
+impl WriteArgs {
+  /// Returns the file to write, OUTPUT or with --in-place FILE. Fails
+  /// unless exactly one of them is given, so nothing is overwritten by
+  /// accident.
+  pub fn destination(&self, file: &Path, output: Option<&PathBuf>) -> Result<PathBuf> {
+    match (output, self.in_place) {
+      (Some(output), false) => Ok(output.clone()),
+      (None, true) => Ok(file.to_path_buf()),
+      (Some(_), true) => Err(usage_error("OUTPUT can't be used with --in-place")),
+      (None, false) => Err(usage_error("no OUTPUT given, use --in-place to overwrite FILE")),
+    }
+  }
+}
+
+/// Target, message and output of encode, see `Cmd::encode_args`.
+pub type EncodeArgs = (Target, Option<String>, Option<PathBuf>);
+
+impl Cmd {
+  /// Returns the target of decode and remove.
+  pub fn target(&self) -> Option<Target> {
+    match self {
+      Cmd::Decode {chunk_type, keyword, ..} => Some(Target::new(chunk_type.clone(), keyword.clone(), false, None)),
+      Cmd::Remove {keyword: Some(keyword), ..} => Some(Target::new(None, Some(keyword.clone()), false, None)),
+      Cmd::Remove {chunk_types, ancillary_only, ..} =>
+        Some(Target::Chunks(ChunkFilter { patterns: chunk_types.clone(), ancillary_only: *ancillary_only })),
+      _ => None,
+    }
+  }
+
+  /// Returns the target, message and output of encode. The positional
+  /// arguments shift when --keyword replaces the chunk type or --input-file
+  /// or --stdin replaces the message, so they are only sorted out here.
+  /// Fails if they don't fit.
+  pub fn encode_args(&self) -> Option<Result<EncodeArgs>> {
+    let Cmd::Encode {chunk_type, message, output, keyword, compress, language, input, ..} = self else {
+      return None;
+    };
+
+    let mut values = chunk_type.iter().chain(message).map(OsString::from)
+      .chain(output.iter().map(|output| output.clone().into_os_string()));
+    let chunk_type = if keyword.is_none() { values.next() } else { None };
+    let message = if input.replaces_message() { None } else { values.next() };
+    let output = values.next().map(PathBuf::from);
+    if values.next().is_some() {
+      let expected = match (keyword.is_some(), input.replaces_message()) {
+        (true, true) => "only OUTPUT",
+        (true, false) => "only MESSAGE and OUTPUT",
+        (false, true) => "only CHUNK_TYPE and OUTPUT",
+        (false, false) => unreachable!("clap takes at most three values"),
+      };
+      return Some(Err(usage_error(&format!("with --keyword, --input-file or --stdin {} are expected", expected))));
+    }
+    if message.is_none() && !input.replaces_message() {
+      return Some(Err(usage_error("no MESSAGE given, pass it or use --input-file or --stdin")));
+    }
+    let message = message.map(|message| message.to_string_lossy().into_owned());
+
+    let target = match chunk_type {
+      None => Target::new(None, keyword.clone(), *compress, language.clone()),
+      Some(chunk_type) => match ChunkType::from_str(&chunk_type.to_string_lossy()) {
+        Ok(chunk_type) => Target::Chunk(chunk_type),
+        Err(err) => return Some(Err(err)),
+      },
+    };
+    Some(Ok((target, message, output)))
   }
 }
 
+fn usage_error(reason: &str) -> PngError {
+  PngError::InvalidInput { reason: reason.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_chunk_filter() {
+    let chunk_type = |s| ChunkType::from_str(s).unwrap();
+    let filter = ChunkFilter { patterns: vec![Pattern::new("?[a-z]??").unwrap(), Pattern::new("tEXt").unwrap()], ancillary_only: false };
+    assert!(filter.matches(&chunk_type("RuSt")));
+    assert!(filter.matches(&chunk_type("tEXt")));
+    assert!(!filter.matches(&chunk_type("IDAT")));
+    assert_eq!(filter.to_string(), "?[a-z]??, tEXt");
+
+    let filter = ChunkFilter { patterns: vec![Pattern::new("*").unwrap()], ancillary_only: false };
+    assert!(filter.matches(&chunk_type("RuSt")));
+    assert!(filter.matches(&chunk_type("prIV")));
+    assert!(!filter.matches(&chunk_type("tEXt")));
+    assert!(!filter.matches(&chunk_type("IDAT")));
+    let filter = ChunkFilter { patterns: vec![Pattern::new("*T").unwrap()], ancillary_only: false };
+    assert!(filter.matches(&chunk_type("IDAT")));
+
+    let filter = ChunkFilter { patterns: vec![], ancillary_only: true };
+    assert!(filter.matches(&chunk_type("ruSt")));
+    assert!(!filter.matches(&chunk_type("RuSt")));
+    assert_eq!(filter.to_string(), "* (ancillary only)");
+  }
+}
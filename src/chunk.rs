@@ -1,5 +1,8 @@
-use crate::{Result, Error};
+use std::io::Write;
 
+use crate::{Result, Error, PngError};
+
+use crate::chunk_ref::ChunkRef;
 use crate::chunk_type::ChunkType;
 #[derive(Debug, Clone)]
 pub struct Chunk {
@@ -11,12 +14,24 @@ pub struct Chunk {
 
 
 impl Chunk {
-  /// Creates a new chunk and calculates the crc and length
+  /// Maximum length of the chunk data allowed by the spec, 2^31 - 1.
+  pub const MAX_LENGTH: u32 = (1 << 31) - 1;
+
+  /// Creates a new chunk and calculates the crc and length. The data must
+  /// not be longer than `MAX_LENGTH`.
   pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-    // merge chunk_type and data for calculating crc
-    let mut bytes = chunk_type.bytes().to_vec();
-    bytes.extend(&data);
-    let crc = crc32fast::hash(&bytes);
+    let crc = Chunk::compute_crc(&chunk_type, &data);
+    Chunk {
+      length: data.len() as u32,
+      chunk_type,
+      data,
+      crc
+    }
+  }
+
+  /// Creates a chunk from parts that were already checked, so the crc
+  /// doesn't have to be computed twice.
+  pub(crate) fn from_checked_parts(chunk_type: ChunkType, data: Vec<u8>, crc: u32) -> Chunk {
     Chunk {
       length: data.len() as u32,
       chunk_type,
@@ -45,23 +60,61 @@ impl Chunk {
     self.crc
   }
 
+  /// Replaces the chunk data and recalculates length and crc. Data longer
+  /// than `MAX_LENGTH` is refused and the chunk is left as it was.
+  pub fn set_data(&mut self, data: Vec<u8>) -> Result<()> {
+    if data.len() > Chunk::MAX_LENGTH as usize {
+      return Err(invalid(&self.chunk_type.to_string(),
+        format!("data has {} bytes, the maximum is {}", data.len(), Chunk::MAX_LENGTH)));
+    }
+    self.crc = Chunk::compute_crc(&self.chunk_type, &data);
+    self.length = data.len() as u32;
+    self.data = data;
+    Ok(())
+  }
+
   /// Returns the chunk bytes as utf8 string
   pub fn data_as_string(&self) -> Result<String> {
     match String::from_utf8(self.data.clone()) {
-      Ok(res) => return Ok(res),
-      Err(res) => return Err(Box::new(std::fmt::Error))
+      Ok(res) => Ok(res),
+      Err(err) => Err(PngError::InvalidUtf8 {
+        chunk_type: self.chunk_type.to_string(),
+        valid_up_to: err.utf8_error().valid_up_to(),
+      })
     }
   }
 
   /// Returns the chunk as bytes
   pub fn as_bytes(&self) -> Vec<u8> {
-    let mut bytes = Vec::<u8>::new();
+    let mut bytes = Vec::<u8>::with_capacity(self.data.len() + 12);
     bytes.extend_from_slice(&self.length.to_be_bytes());
     bytes.extend_from_slice(&self.chunk_type.bytes());
     bytes.extend(&self.data);
     bytes.extend_from_slice(&self.crc.to_be_bytes());
     bytes
   }
+
+  /// Writes the chunk to the writer without building the bytes in memory first.
+  pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+    writer.write_all(&self.length.to_be_bytes())?;
+    writer.write_all(&self.chunk_type.bytes())?;
+    writer.write_all(&self.data)?;
+    writer.write_all(&self.crc.to_be_bytes())?;
+    Ok(())
+  }
+
+  /// Computes the crc over chunk type and data, without joining them.
+  pub fn compute_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&chunk_type.bytes());
+    hasher.update(data);
+    hasher.finalize()
+  }
+
+  /// Parses the chunk at the start of `bytes`, see `ChunkRef::from_bytes_at`.
+  pub(crate) fn from_bytes_at(bytes: &[u8], index: usize, offset: usize) -> Result<Chunk> {
+    ChunkRef::from_bytes_at(bytes, index, offset).map(|chunk| chunk.to_chunk())
+  }
 }
 
 /// Try to convert slice of bytes to chunk, assumes the bytes
@@ -69,21 +122,7 @@ impl Chunk {
 impl TryFrom<&[u8]> for Chunk {
   type Error = Error;
   fn try_from(bytes: &[u8]) -> Result<Self> {
-    if bytes.len() < 12 {
-      return Err(Box::new(std::fmt::Error));
-    }
-
-    let checksum = crc32fast::hash(&bytes[4..bytes.len() - 4]);
-    if checksum != u32::from_be_bytes(array4_from_slice(&bytes[bytes.len() - 4..])) {
-      return Err(Box::new(std::fmt::Error));
-    }
-    
-    Ok(Chunk {
-      length: u32::from_be_bytes(array4_from_slice(&bytes[..4])),
-      chunk_type: ChunkType::try_from(array4_from_slice(&bytes[4..8]))?,
-      data: bytes[8..bytes.len() - 4].to_vec(),
-      crc: u32::from_be_bytes(array4_from_slice(&bytes[bytes.len() - 4..])),
-    })
+    Chunk::from_bytes_at(bytes, 0, 0)
   }
 }
 
@@ -105,13 +144,23 @@ pub fn array4_from_slice(slice: &[u8]) -> [u8; 4] {
   arr
 }
 
+/// Error for chunk data that breaks the rules of its chunk type.
+pub(crate) fn invalid(chunk_type: &str, reason: impl Into<String>) -> PngError {
+  PngError::InvalidChunkData { chunk_type: chunk_type.to_string(), reason: reason.into() }
+}
+
+/// Fails unless the chunk has the expected type.
+pub(crate) fn check_type(chunk: &Chunk, expected: &str) -> Result<()> {
+  if chunk.chunk_type().bytes() != expected.as_bytes() {
+    return Err(invalid(expected, format!("expected a {} chunk, got {}", expected, chunk.chunk_type())));
+  }
+  Ok(())
+}
 
 
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::chunk_type::ChunkType;
-  use std::str::FromStr;
 
   fn testing_chunk() -> Chunk {
     let data_length: u32 = 42;
@@ -205,6 +254,50 @@ mod tests {
     assert!(chunk.is_err());
   }
 
+  #[test]
+  fn test_crc_mismatch_error() {
+    let mut chunk_data = testing_chunk().as_bytes();
+    let last = chunk_data.len() - 1;
+    chunk_data[last] ^= 1;
+
+    match Chunk::try_from(chunk_data.as_ref()) {
+      Err(PngError::CrcMismatch { expected, actual, .. }) => {
+        assert_eq!(expected, 2882656334 ^ 1);
+        assert_eq!(actual, 2882656334);
+      },
+      other => panic!("expected crc mismatch, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_invalid_utf8_error() {
+    let chunk = Chunk::new(ChunkType::try_from(*b"RuSt").unwrap(), vec![b'o', b'k', 0xff]);
+    assert!(matches!(chunk.data_as_string(), Err(PngError::InvalidUtf8 { valid_up_to: 2, .. })));
+  }
+
+  #[test]
+  fn test_truncated_chunk_error() {
+    let chunk_data = testing_chunk().as_bytes();
+    for len in 0..chunk_data.len() {
+      assert!(matches!(Chunk::try_from(&chunk_data[..len]), Err(PngError::TruncatedChunk { .. })));
+    }
+  }
+
+  #[test]
+  fn test_chunk_too_long_error() {
+    let mut chunk_data = testing_chunk().as_bytes();
+    chunk_data[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(matches!(Chunk::try_from(chunk_data.as_ref()), Err(PngError::ChunkTooLong { length: u32::MAX, .. })));
+  }
+
+  #[test]
+  fn test_write_to() {
+    let chunk = testing_chunk();
+    let mut bytes = Vec::new();
+    chunk.write_to(&mut bytes).unwrap();
+    assert_eq!(bytes, chunk.as_bytes());
+  }
+
   #[test]
   pub fn test_chunk_trait_impls() {
     let data_length: u32 = 42;
@@ -225,4 +318,18 @@ mod tests {
     
     let _chunk_string = format!("{}", chunk);
   }
+
+  #[test]
+  fn test_set_data() {
+    let mut chunk = testing_chunk();
+    chunk.set_data(b"another message".to_vec()).unwrap();
+    assert_eq!(chunk.length(), 15);
+    assert_eq!(chunk.data_as_string().unwrap(), "another message");
+    assert_eq!(chunk.as_bytes(), Chunk::new(chunk.chunk_type().clone(), b"another message".to_vec()).as_bytes());
+
+    // zeroed memory is only mapped when it is touched, so this stays cheap
+    let too_long = vec![0; Chunk::MAX_LENGTH as usize + 1];
+    assert!(matches!(chunk.set_data(too_long), Err(PngError::InvalidChunkData { .. })));
+    assert_eq!(chunk.data_as_string().unwrap(), "another message");
+  }
 }
\ No newline at end of file
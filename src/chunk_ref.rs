@@ -0,0 +1,145 @@
+use crate::chunk::{Chunk, array4_from_slice};
+use crate::chunk_type::ChunkType;
+use crate::{Error, PngError, Result};
+
+/// A chunk that borrows its data from the parsed input instead of owning a
+/// copy. Use `to_chunk` when the chunk has to outlive the input or be changed.
+#[derive(Debug, Clone)]
+pub struct ChunkRef<'a> {
+  chunk_type: ChunkType,
+  data: &'a [u8],
+  crc: u32,
+}
+
+impl<'a> ChunkRef<'a> {
+  /// Returns the length of the chunk data
+  pub fn length(&self) -> u32 {
+    self.data.len() as u32
+  }
+
+  /// Returns the chunk type
+  pub fn chunk_type(&self) -> &ChunkType {
+    &self.chunk_type
+  }
+
+  /// Returns the chunk data, borrowed from the input
+  pub fn data(&self) -> &'a [u8] {
+    self.data
+  }
+
+  /// Returns the crc of the chunk
+  pub fn crc(&self) -> u32 {
+    self.crc
+  }
+
+  /// Returns the chunk data as utf8 str without copying it
+  pub fn data_as_str(&self) -> Result<&'a str> {
+    std::str::from_utf8(self.data).map_err(|err| PngError::InvalidUtf8 {
+      chunk_type: self.chunk_type.to_string(),
+      valid_up_to: err.valid_up_to(),
+    })
+  }
+
+  /// Copies the data into an owned chunk.
+  pub fn to_chunk(&self) -> Chunk {
+    Chunk::from_checked_parts(self.chunk_type.clone(), self.data.to_vec(), self.crc)
+  }
+
+  /// Parses the chunk at the start of `bytes`, anything after its crc is
+  /// ignored. `index` and `offset` are the position of the chunk in the file
+  /// and only used for error reporting. Never panics, whatever the input.
+  pub(crate) fn from_bytes_at(bytes: &'a [u8], index: usize, offset: usize) -> Result<ChunkRef<'a>> {
+    if bytes.len() < 12 {
+      return Err(PngError::TruncatedChunk { index, offset, needed: 12, available: bytes.len() });
+    }
+
+    let length = u32::from_be_bytes(array4_from_slice(&bytes[..4]));
+    if length > Chunk::MAX_LENGTH {
+      return Err(PngError::ChunkTooLong { index, offset, length });
+    }
+    // length (4) + type (4) + data + crc (4), can't overflow since length < 2^31
+    let total = length as usize + 12;
+    if bytes.len() < total {
+      return Err(PngError::TruncatedChunk { index, offset, needed: total, available: bytes.len() });
+    }
+
+    let crc = u32::from_be_bytes(array4_from_slice(&bytes[total - 4..total]));
+    let checksum = crc32fast::hash(&bytes[4..total - 4]);
+    if checksum != crc {
+      return Err(PngError::CrcMismatch { index, offset, expected: crc, actual: checksum });
+    }
+
+    let chunk_type = ChunkType::try_from(array4_from_slice(&bytes[4..8])).map_err(|_| {
+      PngError::InvalidChunkType { bytes: bytes[4..8].to_vec(), index: Some(index), offset: Some(offset) }
+    })?;
+
+    Ok(ChunkRef {
+      chunk_type,
+      data: &bytes[8..total - 4],
+      crc,
+    })
+  }
+}
+
+/// Borrow the chunk at the start of the slice, assumes the bytes
+/// are the complete chunk including the crc
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+  type Error = Error;
+  fn try_from(bytes: &'a [u8]) -> Result<Self> {
+    ChunkRef::from_bytes_at(bytes, 0, 0)
+  }
+}
+
+impl<'a> From<ChunkRef<'a>> for Chunk {
+  fn from(chunk: ChunkRef<'a>) -> Self {
+    chunk.to_chunk()
+  }
+}
+
+impl<'a> From<&'a Chunk> for ChunkRef<'a> {
+  fn from(chunk: &'a Chunk) -> Self {
+    ChunkRef {
+      chunk_type: chunk.chunk_type().clone(),
+      data: chunk.data(),
+      crc: chunk.crc(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  fn testing_bytes() -> Vec<u8> {
+    let chunk_type = ChunkType::from_str("RuSt").unwrap();
+    Chunk::new(chunk_type, b"This is where your secret message will be!".to_vec()).as_bytes()
+  }
+
+  #[test]
+  fn test_chunk_ref_borrows_data() {
+    let bytes = testing_bytes();
+    let chunk = ChunkRef::try_from(bytes.as_slice()).unwrap();
+    assert_eq!(chunk.length(), 42);
+    assert_eq!(chunk.crc(), 2882656334);
+    assert_eq!(chunk.data_as_str().unwrap(), "This is where your secret message will be!");
+    assert!(std::ptr::eq(chunk.data(), &bytes[8..50]));
+  }
+
+  #[test]
+  fn test_chunk_ref_to_chunk() {
+    let bytes = testing_bytes();
+    let chunk: Chunk = ChunkRef::try_from(bytes.as_slice()).unwrap().into();
+    assert_eq!(chunk.as_bytes(), bytes);
+
+    let chunk_ref = ChunkRef::from(&chunk);
+    assert_eq!(chunk_ref.data(), chunk.data());
+  }
+
+  #[test]
+  fn test_invalid_chunk_ref() {
+    let mut bytes = testing_bytes();
+    bytes[10] ^= 1;
+    assert!(matches!(ChunkRef::try_from(bytes.as_slice()), Err(PngError::CrcMismatch { .. })));
+  }
+}
@@ -1,5 +1,5 @@
 use std::{str::FromStr};
-use crate::{Result, Error};
+use crate::{Result, Error, PngError};
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct ChunkType {
@@ -9,7 +9,7 @@ pub struct ChunkType {
 /// 4-bytes that identify the type of the chunk. As described
 /// in the spec: http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
 impl ChunkType {
-  pub fn new(&self, bytes: [u8; 4]) -> Self {
+  pub(crate) fn new(bytes: [u8; 4]) -> Self {
     ChunkType {
         bytes
     }
@@ -21,31 +21,31 @@ impl ChunkType {
   }
 
   /// adheres to spec
-  fn is_valid(&self) -> bool {
+  pub fn is_valid(&self) -> bool {
     if !self.bytes.iter().all(|&b| b.is_ascii_alphabetic()) {
         return false;
     }
-    return self.is_reserved_bit_valid();
+    self.is_reserved_bit_valid()
   }
 
   /// Ancillary bit (5th bit) 0 = critical, 1 = ancillary
-  fn is_critical(&self) -> bool{
+  pub fn is_critical(&self) -> bool{
     self.bytes[0] & 0b00100000u8 != 0b00100000u8
 
   }
 
   /// Private bit (5th bit) 0 = public, 1 = private
-  fn is_public(&self) -> bool {
+  pub fn is_public(&self) -> bool {
     self.bytes[1] & 0b00100000u8 != 0b00100000u8
   }
 
   /// Reserved bit (5th bit) 0 = reserved, 1 = not reserved (must be 0)
-  fn is_reserved_bit_valid(&self) -> bool {
+  pub fn is_reserved_bit_valid(&self) -> bool {
     self.bytes[2] & 0b00100000u8 != 0b00100000u8
   }
 
   /// Safe-to-copy bit (5th bit) 0 = unsafe to copy, 1 = safe to copy
-  fn is_safe_to_copy(&self) -> bool {
+  pub fn is_safe_to_copy(&self) -> bool {
     self.bytes[3] & 0b00100000u8 == 0b00100000u8
   }
 }
@@ -56,7 +56,7 @@ impl TryFrom<[u8; 4]> for ChunkType {
   fn try_from(bytes: [u8; 4]) -> Result<Self> {
     for byte in &bytes {
       if !byte.is_ascii_alphabetic() {
-          return Err(Box::new(std::fmt::Error));
+          return Err(PngError::invalid_chunk_type(&bytes));
       }
     }
     Ok(ChunkType {
@@ -70,11 +70,11 @@ impl TryFrom<&str> for ChunkType {
   fn try_from(s: &str) -> Result<Self> {
     let bytes = s.as_bytes();
     if bytes.len() != 4 {
-      return Err(Box::new(std::fmt::Error));
+      return Err(PngError::invalid_chunk_type(bytes));
     }
     for byte in bytes {
       if !byte.is_ascii_alphabetic() {
-          return Err(Box::new(std::fmt::Error));
+          return Err(PngError::invalid_chunk_type(bytes));
       }
     }
     Ok(ChunkType {
@@ -88,11 +88,11 @@ impl FromStr for ChunkType {
 
   fn from_str(s: &str) -> Result<Self> {
     if s.len() != 4 {
-      return Err(Box::new(std::fmt::Error));
+      return Err(PngError::invalid_chunk_type(s.as_bytes()));
     }
     for byte in s.as_bytes() {
       if !byte.is_ascii_alphabetic() {
-          return Err(Box::new(std::fmt::Error));
+          return Err(PngError::invalid_chunk_type(s.as_bytes()));
       }
     }
 
@@ -102,9 +102,9 @@ impl FromStr for ChunkType {
   }
 }
 
-/// FIXME added this for making #[clap(parse(from_str))] work
-/// but since this op can panic TryFrom is the better trait,
-/// which I couldn't get working in parse()
+// FIXME added this for making #[clap(parse(from_str))] work
+// but since this op can panic TryFrom is the better trait,
+// which I couldn't get working in parse()
 // impl From<&str> for ChunkType {
 //   fn from(s: &str) -> Self {
 //     ChunkType {
@@ -1,66 +1,376 @@
+use std::io::Write;
 use std::{ path::PathBuf };
-use std::io::prelude::*;
-use crate::chunk::Chunk;
-use crate::png::Png;
-use crate::{ Result };
-use crate::chunk_type::ChunkType;
+use serde_json::{ json, Value };
+use pngproject::chunk::Chunk;
+use pngproject::png::Png;
+use pngproject::{ PngError, Result };
+use pngproject::chunk_type::ChunkType;
+use pngproject::envelope;
+use pngproject::inspect::{self, Inspection, ReportOptions};
+use pngproject::position::Position;
+use pngproject::split;
+use pngproject::text::{InternationalTextChunk, Text};
+use pngproject::validate;
 
-/// FIXME to utils or chunks
-fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk> {
-  use std::str::FromStr;
+use crate::args::{Encoding, Format, OutputArgs, Target, WriteArgs};
+use crate::{files, payload};
 
-  let chunk_type = ChunkType::from_str(chunk_type)?;
-  let data: Vec<u8> = data.bytes().collect();
+/// Chunks the image can't do without, remove refuses them unless forced.
+const CRITICAL: [[u8; 4]; 2] = [*b"PLTE", *b"IDAT"];
 
-  Ok(Chunk::new(chunk_type, data))
-}
+/// Chunks every png has exactly one of, a file without them can't be
+/// written at all, so remove refuses them even when forced.
+const REQUIRED: [[u8; 4]; 2] = [*b"IHDR", *b"IEND"];
 
 /// Create png object from file.
 fn read_png(file: &PathBuf) -> Result<Png> {
-  let mut file = std::fs::File::open(file).unwrap();
-  let mut buffer = Vec::<u8>::new();
-  file.read_to_end(&mut buffer).unwrap();
-  let png = Png::try_from(buffer.as_slice()).unwrap();
-  Ok(png)
+  let file = std::fs::File::open(file)?;
+  Png::from_reader(std::io::BufReader::new(file))
 }
 
-/// Insert a chunk with type and message into the given png file and writhe to output file.
-pub fn encode(file: &PathBuf, chunk_type: &ChunkType, message: &str, output: &Option<PathBuf>) {
-  println!("{:?} {} {} {:?}", file, chunk_type, message, output);
-  let mut png = read_png(file).unwrap();
+/// Print the outcome of a command, `text` for people or `json` for scripts.
+fn output(format: Format, text: &str, json: Value) -> Result<()> {
+  let mut line = match format {
+    Format::Text => text.to_string(),
+    Format::Json => serde_json::to_string_pretty(&json).unwrap(),
+  };
+  line.push('\n');
+  write_stdout(line.as_bytes())
+}
 
-  // create chunk from type and message
-  let chunk = chunk_from_strings(&chunk_type.to_string(), message).unwrap();
+/// Write to stdout. A reader that stops early, like `head`, is not an
+/// error.
+fn write_stdout(data: &[u8]) -> Result<()> {
+  let mut stdout = std::io::stdout().lock();
+  match stdout.write_all(data).and_then(|_| stdout.flush()) {
+    Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+    result => Ok(result?),
+  }
+}
 
-  // insert at second to last pos
-  png.insert_chunk(chunk);
+/// Print an error to stderr in the requested format.
+pub fn report_error(format: Format, err: &PngError) {
+  match format {
+    Format::Text => eprintln!("error: {}", err),
+    Format::Json => eprintln!("{}", json!({ "error": error_json(err) })),
+  }
+}
 
-  std::fs::write("./test.png", png.as_bytes()).unwrap();
+fn error_json(err: &PngError) -> Value {
+  match err {
+    PngError::InvalidStructure { violations } => json!({
+      "kind": err.kind(),
+      "message": err.to_string(),
+      "violations": violations.iter().map(error_json).collect::<Vec<_>>(),
+    }),
+    _ => json!({ "kind": err.kind(), "message": err.to_string() }),
+  }
+}
+
+fn chunk_json(chunk: &Chunk) -> Value {
+  json!({ "type": chunk.chunk_type().to_string(), "length": chunk.length(), "crc": chunk.crc() })
+}
+
+fn text_json(text: &Text) -> Value {
+  let (chunk_type, compressed, language) = match text {
+    Text::Plain(_) => ("tEXt", false, ""),
+    Text::Compressed(_) => ("zTXt", true, ""),
+    Text::International(t) => ("iTXt", t.is_compressed(), t.language_tag()),
+  };
+  json!({
+    "type": chunk_type,
+    "keyword": text.keyword(),
+    "compressed": compressed,
+    "language": language,
+    "text": text.text(),
+  })
+}
+
+/// Chunk data as text if it is utf8 and as hex, both cut short unless full
+/// is asked for.
+fn data_json(data: &[u8], options: &ReportOptions) -> Value {
+  let shown = if options.full { data } else { &data[..data.len().min(inspect::PREVIEW_BYTES)] };
+  json!({
+    "text": std::str::from_utf8(data).ok().map(|text| inspect::truncate(text, options)),
+    "hex": inspect::hex(shown),
+    "truncated": shown.len() < data.len(),
+  })
+}
+
+/// Insert a chunk with type and message at the position into the given png file and
+/// write to output file. Messages longer than max_chunk_size are split over several
+/// chunks that follow each other. With a keyword the message is stored as iTXt text
+/// instead, it must be utf8 then.
+#[allow(clippy::too_many_arguments)]
+pub fn encode(file: &PathBuf, target: &Target, message: &[u8], position: &Position, max_chunk_size: usize,
+  destination: &PathBuf, write: &WriteArgs, format: Format) -> Result<()> {
+  let mut png = read_png(file)?;
+
+  let (text, added) = match target {
+    Target::Chunk(chunk_type) => {
+      if !chunk_type.is_valid() {
+        return Err(PngError::InvalidInput {
+          reason: format!("chunk type {} has the reserved bit set, its third letter must be uppercase", chunk_type),
+        });
+      }
+      let mut chunks = split::split(chunk_type, message, max_chunk_size)?;
+      let added: Vec<Value> = chunks.iter().map(chunk_json).collect();
+
+      let pieces = chunks.len();
+      let index = png.insert_chunk_at(chunks.remove(0), position.clone())?;
+      for (i, chunk) in chunks.into_iter().enumerate() {
+        png.insert_chunk_at(chunk, Position::Index(index + i + 1))?;
+      }
+      let text = match pieces {
+        1 => format!("added {} chunk of {} bytes at index {}", chunk_type, message.len(), index),
+        _ => format!("added {} bytes in {} {} chunks at index {}", message.len(), pieces, chunk_type, index),
+      };
+      let added = match pieces {
+        1 => json!({ "chunk": added[0], "index": index }),
+        _ => json!({ "chunks": added, "index": index }),
+      };
+      (text, added)
+    },
+    Target::Keyword {keyword, compress, language} => {
+      let message = payload::to_text(message, Encoding::Text, "iTXt")?;
+      let text = InternationalTextChunk::new(keyword, &message)?
+        .with_compression(*compress)?
+        .with_language(language.as_deref().unwrap_or_default(), "")?;
+      let text = Text::International(text);
+      let added = json!({ "chunk": chunk_json(&text.to_chunk()), "text": text_json(&text) });
+      png.insert_text(text);
+      (format!("added iTXt text {:?}", keyword), added)
+    },
+    Target::Chunks(_) => unreachable!("only remove takes several chunk types"),
+  };
+
+  let backup = files::write_png(&png, file, destination, write)?;
+  output(format, &format!("{} to {}", text, destination.display()), json!({
+    "command": "encode",
+    "file": file,
+    "output": destination,
+    "backup": backup,
+    "added": added,
+  }))?;
+  Ok(())
 }
 
 /// Read a chunk with chunk_type from given png and print chunk data.
-pub fn decode(file: &PathBuf, chunk_type: &ChunkType) {
-  println!("{:?} {}", file, chunk_type);
+/// With a keyword the matching text is printed. The data is written as
+/// text, hex or base64, or as it is to stdout or a file. Encrypted data is
+/// decrypted first if asked for.
+pub fn decode(file: &PathBuf, target: &Target, options: &OutputArgs, format: Format) -> Result<()> {
+  let png = read_png(file)?;
+
+  let (data, chunk_type, found) = match target {
+    Target::Chunk(chunk_type) => {
+      let chunks = png.chunks_by_type(&chunk_type.to_string());
+      let (pieces, plain): (Vec<&Chunk>, Vec<&Chunk>) = chunks.iter().partition(|c| split::is_piece(c));
+      if !pieces.is_empty() {
+        let joined = split::join(&pieces)?;
+        if !joined.in_order() && format == Format::Text {
+          eprintln!("warning: the {} pieces are out of order in the file, joined by their index", chunk_type);
+        }
+        if !plain.is_empty() && format == Format::Text {
+          let plural = if plain.len() == 1 { "" } else { "s" };
+          eprintln!("warning: ignored {} {} chunk{} that {} not part of the split payload", plain.len(), chunk_type, plural,
+            if plain.len() == 1 { "is" } else { "are" });
+        }
+        let found = json!({
+          "chunks": pieces.iter().map(|c| chunk_json(c)).collect::<Vec<_>>(),
+          "order": joined.order,
+          "in_order": joined.in_order(),
+          "ignored": plain.iter().map(|c| chunk_json(c)).collect::<Vec<_>>(),
+        });
+        (joined.data, chunk_type.to_string(), found)
+      } else {
+        let chunk = chunks.first()
+          .ok_or_else(|| PngError::ChunkNotFound { chunk_type: chunk_type.to_string() })?;
+        (chunk.data().to_vec(), chunk_type.to_string(), json!({ "chunk": chunk_json(chunk) }))
+      }
+    },
+    Target::Keyword {keyword, ..} => {
+      let text = png.text(keyword)?;
+      (text.text().as_bytes().to_vec(), text.to_chunk().chunk_type().to_string(), json!({ "text": text_json(&text) }))
+    },
+    Target::Chunks(_) => unreachable!("only remove takes several chunk types"),
+  };
+
+  let data = match (options.decrypt, envelope::is_sealed(&data)) {
+    (true, _) => envelope::open(&data, payload::password(false)?.as_bytes())?,
+    (false, true) if options.encoding() == Encoding::Text =>
+      return Err(PngError::InvalidInput { reason: format!("the {} data is encrypted, use --decrypt", chunk_type) }),
+    (false, _) => data,
+  };
+
+  let encoding = options.encoding();
+  if let Some(path) = &options.output_file {
+    match encoding {
+      Encoding::Raw => std::fs::write(path, &data)?,
+      _ => std::fs::write(path, payload::to_text(&data, encoding, &chunk_type)?)?,
+    }
+    output(format, &format!("wrote {} bytes of {} to {}", data.len(), chunk_type, path.display()), json!({
+      "command": "decode",
+      "file": file,
+      "found": found,
+      "output_file": path,
+      "length": data.len(),
+    }))?;
+    return Ok(());
+  }
+
+  if encoding == Encoding::Raw {
+    if format == Format::Json {
+      return Err(PngError::InvalidInput { reason: "--raw can't be combined with --format json, use --hex or --base64".to_string() });
+    }
+    return write_stdout(&data);
+  }
 
-  let png = read_png(file).unwrap();
+  let message = payload::to_text(&data, encoding, &chunk_type)?;
+  output(format, &message, json!({
+    "command": "decode",
+    "file": file,
+    "found": found,
+    "encoding": match encoding { Encoding::Hex => "hex", Encoding::Base64 => "base64", _ => "utf8" },
+    "message": message,
+  }))?;
+  Ok(())
+}
+
+/// Remove all chunks that match, or all texts with the keyword. PLTE and
+/// IDAT are only removed with force, IHDR and IEND never.
+pub fn remove(file: &PathBuf, target: &Target, force: bool, destination: &PathBuf, write: &WriteArgs, format: Format) -> Result<()> {
+  let mut png = read_png(file)?;
 
-  let chunk = png.chunk_by_type(&chunk_type.to_string()).unwrap();
+  let (removed, what): (Vec<Value>, _) = match target {
+    Target::Chunks(filter) => {
+      let refused = png.chunks().iter()
+        .map(Chunk::chunk_type)
+        .filter(|chunk_type| filter.matches(chunk_type))
+        .find_map(|chunk_type| match chunk_type.bytes() {
+          bytes if REQUIRED.contains(&bytes) => Some((chunk_type, "every png needs one, not even --force removes it")),
+          bytes if CRITICAL.contains(&bytes) && !force => Some((chunk_type, "it is critical, use --force to remove it anyway")),
+          _ => None,
+        });
+      if let Some((chunk_type, reason)) = refused {
+        return Err(PngError::CriticalChunk { chunk_type: chunk_type.to_string(), reason: reason.to_string() });
+      }
+      let removed = png.remove_chunks_where(|chunk| filter.matches(chunk.chunk_type()));
+      if removed.is_empty() {
+        return Err(PngError::ChunkNotFound { chunk_type: filter.to_string() });
+      }
+      (removed.iter().map(chunk_json).collect(), ("chunk", format!("matching {}", filter)))
+    },
+    Target::Keyword {keyword, ..} =>
+      (png.remove_text(keyword)?.iter().map(text_json).collect(), ("text", format!("with keyword {:?}", keyword))),
+    Target::Chunk(_) => unreachable!("remove always takes chunk types as patterns"),
+  };
 
-  println!("{}", chunk.data_as_string().unwrap());
+  // forced removal of critical chunks breaks the structure on purpose
+  let backup = match force {
+    true => files::write_png_unchecked(&png, file, destination, write)?,
+    false => files::write_png(&png, file, destination, write)?,
+  };
+  let (noun, matching) = what;
+  let plural = if removed.len() == 1 { "" } else { "s" };
+  output(format, &format!("removed {} {}{} {}, written to {}", removed.len(), noun, plural, matching, destination.display()), json!({
+    "command": "remove",
+    "file": file,
+    "output": destination,
+    "backup": backup,
+    "removed": removed,
+  }))?;
+  Ok(())
 }
 
-pub fn remove(file: &PathBuf, chunk_type: &ChunkType) {
-  println!("{:?} {}", file, chunk_type);
+/// Replace the data of the index-th chunk with chunk_type by the message,
+/// the chunk stays where it is.
+pub fn update(file: &PathBuf, chunk_type: &ChunkType, message: &str, index: usize, destination: &PathBuf, write: &WriteArgs, format: Format) -> Result<()> {
+  let mut png = read_png(file)?;
+
+  let chunk = png.nth_chunk_mut(&chunk_type.to_string(), index)?;
+  let old = chunk_json(chunk);
+  chunk.set_data(message.as_bytes().to_vec())?;
+  let new = chunk_json(chunk);
 
-  let mut png = read_png(file).unwrap();
+  let backup = files::write_png(&png, file, destination, write)?;
+  output(format, &format!("updated {} chunk {} to {} bytes, written to {}", chunk_type, index, message.len(), destination.display()), json!({
+    "command": "update",
+    "file": file,
+    "output": destination,
+    "backup": backup,
+    "index": index,
+    "old": old,
+    "new": new,
+  }))?;
+  Ok(())
+}
+
+/// Print a summary of the signature and every chunk. Damaged files are
+/// shown as far as they can be read.
+pub fn print(file: &PathBuf, full: bool, hex: bool, format: Format) -> Result<()> {
+  let bytes = std::fs::read(file)?;
+  let inspection = Inspection::new(&bytes);
+  let options = ReportOptions { full, hex };
 
-  png.remove_chunk(&chunk_type.to_string()).unwrap();
+  let ihdr = inspection.header();
+  let palette = inspection.palette();
+  let chunks: Vec<Value> = inspection.chunks.iter().map(|chunk| {
+    let chunk_type = &chunk.chunk_type;
+    let description = chunk.to_chunk()
+      .and_then(|c| inspect::describe(&c, ihdr.as_ref(), palette.as_ref(), &options));
+    json!({
+      "index": chunk.index,
+      "offset": chunk.offset,
+      "type": chunk.type_name(),
+      "valid_type": chunk.to_chunk().is_some(),
+      "critical": chunk_type.is_critical(),
+      "public": chunk_type.is_public(),
+      "reserved_bit_valid": chunk_type.is_reserved_bit_valid(),
+      "safe_to_copy": chunk_type.is_safe_to_copy(),
+      "length": chunk.data.len(),
+      "crc": { "stored": chunk.stored_crc, "computed": chunk.computed_crc, "ok": chunk.crc_ok() },
+      "data": (description.is_none() || hex).then(|| data_json(chunk.data, &options)),
+      "description": description,
+    })
+  }).collect();
 
-  std::fs::write("./test.png", png.as_bytes()).unwrap();
+  output(format, inspection.report(&options).trim_end(), json!({
+    "command": "print",
+    "file": file,
+    "signature": { "ok": inspection.signature_ok(), "bytes": inspection.signature },
+    "chunks": chunks,
+    "error": inspection.error.as_ref().map(error_json),
+  }))?;
+  Ok(())
 }
 
-pub fn print(file: &PathBuf) {
-  let png = read_png(file).unwrap();
+/// Check every file and print its problems. Files that can't be read are
+/// reported like any other problem. Returns whether all files are valid.
+pub fn validate(files: &[PathBuf], format: Format) -> Result<bool> {
+  let results: Vec<(&PathBuf, Vec<PngError>)> = files.iter()
+    .map(|file| (file, std::fs::read(file).map_or_else(|err| vec![err.into()], |bytes| validate::check_bytes(&bytes))))
+    .collect();
+  let valid = results.iter().all(|(_, problems)| problems.is_empty());
 
-  println!("{}", png);
-}
\ No newline at end of file
+  let mut text = String::new();
+  for (file, problems) in &results {
+    match problems.len() {
+      0 => text.push_str(&format!("{}: ok\n", file.display())),
+      n => {
+        text.push_str(&format!("{}: {} problem{}\n", file.display(), n, if n == 1 { "" } else { "s" }));
+        problems.iter().for_each(|problem| text.push_str(&format!("  {}\n", problem.to_string().replace('\n', "\n  "))));
+      },
+    }
+  }
+  output(format, text.trim_end(), json!({
+    "command": "validate",
+    "valid": valid,
+    "files": results.iter().map(|(file, problems)| json!({
+      "file": file,
+      "valid": problems.is_empty(),
+      "problems": problems.iter().map(error_json).collect::<Vec<_>>(),
+    })).collect::<Vec<_>>(),
+  }))?;
+  Ok(valid)
+}
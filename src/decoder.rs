@@ -0,0 +1,298 @@
+use crate::adam7;
+use crate::filter::{self, FilterType};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::image::ImageBuffer;
+use crate::palette::{Palette, Transparency};
+use crate::png::Png;
+use crate::{PngError, Result};
+
+/// Decodes the pixels of a png: inflates the IDAT chunks, reverses the
+/// scanline filters and unpacks sub-byte samples into the `ImageBuffer` layout.
+/// Adam7 interlaced images are put back together.
+pub fn decode(png: &Png) -> Result<ImageBuffer> {
+  let ihdr = png.header_info()?;
+  let data = png.image_data()?;
+  if !ihdr.is_interlaced() {
+    return decode_scanlines(&data, &ihdr, ihdr.width, ihdr.height);
+  }
+
+  let passes = adam7::decode_passes(&data, &ihdr)?;
+  if passes.len() < adam7::PASSES.len() {
+    return Err(PngError::InvalidImageData {
+      reason: format!("image data has {} bytes, expected {:?}", data.len(), expected_data_len(&ihdr)),
+    });
+  }
+  adam7::deinterlace(&passes, &ihdr)
+}
+
+/// Decodes the pixels like `decode`, but indexed images are expanded to
+/// 8-bit rgba with the PLTE and tRNS chunks. Other color types are returned
+/// as they are.
+pub fn decode_rgba(png: &Png) -> Result<ImageBuffer> {
+  let image = decode(png)?;
+  if image.color_type() != ColorType::Indexed {
+    return Ok(image);
+  }
+  let palette = png.palette()?.ok_or_else(|| PngError::ChunkNotFound { chunk_type: "PLTE".to_string() })?;
+  expand_palette(&image, &palette, png.transparency()?.as_ref())
+}
+
+/// Looks up every palette index of an indexed image. Entries without an
+/// alpha in the transparency are opaque.
+pub fn expand_palette(image: &ImageBuffer, palette: &Palette, transparency: Option<&Transparency>) -> Result<ImageBuffer> {
+  if image.color_type() != ColorType::Indexed {
+    return Err(PngError::InvalidImageData { reason: format!("can't look up a palette for color type {}", image.color_type()) });
+  }
+  let alphas = match transparency {
+    Some(Transparency::Indexed(alphas)) => alphas.as_slice(),
+    _ => &[],
+  };
+
+  let mut data = Vec::with_capacity(image.data().len() * 4);
+  for &index in image.data() {
+    let [r, g, b] = *palette.entries.get(index as usize).ok_or_else(|| PngError::InvalidImageData {
+      reason: format!("palette index {} is out of range, the palette has {} entries", index, palette.entries.len()),
+    })?;
+    data.extend_from_slice(&[r, g, b, alphas.get(index as usize).copied().unwrap_or(255)]);
+  }
+  ImageBuffer::new(image.width(), image.height(), ColorType::Rgba, 8, data)
+}
+
+/// Decodes the seven reduced images of an interlaced png, see
+/// `adam7::decode_passes`.
+pub fn decode_passes(png: &Png) -> Result<Vec<ImageBuffer>> {
+  let ihdr = png.header_info()?;
+  if !ihdr.is_interlaced() {
+    return Err(PngError::InvalidImageData { reason: "image is not interlaced".to_string() });
+  }
+  adam7::decode_passes(&png.image_data()?, &ihdr)
+}
+
+/// Unfilters and unpacks the `height` scanlines of `width` pixels in
+/// `data`, which is a whole image or a single Adam7 pass.
+pub fn decode_scanlines(data: &[u8], ihdr: &Ihdr, width: u32, height: u32) -> Result<ImageBuffer> {
+  let rows = unfilter_scanlines(data, width, height, ihdr.bits_per_pixel())?;
+  let pixels = unpack_samples(rows, ihdr, width, height);
+  ImageBuffer::new(width, height, ihdr.color_type, ihdr.bit_depth, pixels)
+}
+
+/// Size of the inflated image data the header asks for, filter bytes
+/// included. `None` if it doesn't fit in memory.
+pub fn expected_data_len(ihdr: &Ihdr) -> Option<usize> {
+  if ihdr.is_interlaced() {
+    adam7::filtered_data_len(ihdr)
+  } else {
+    filtered_len(ihdr.width, ihdr.height, ihdr.bits_per_pixel())
+  }
+}
+
+/// Number of bytes in one scanline of `width` pixels, without the filter
+/// byte. `None` if it doesn't fit in memory.
+pub fn scanline_len(width: u32, bits_per_pixel: usize) -> Option<usize> {
+  let bits = (width as usize).checked_mul(bits_per_pixel)?;
+  Some(bits / 8 + usize::from(bits % 8 != 0))
+}
+
+/// Number of bytes of filtered image data for `height` scanlines, including
+/// the filter bytes. `None` if it doesn't fit in memory.
+pub fn filtered_len(width: u32, height: u32, bits_per_pixel: usize) -> Option<usize> {
+  if width == 0 || height == 0 {
+    return Some(0);
+  }
+  scanline_len(width, bits_per_pixel)?.checked_add(1)?.checked_mul(height as usize)
+}
+
+/// Reverses the filters of `height` scanlines, `data` must hold exactly
+/// those. The result are the packed scanlines without filter bytes.
+pub fn unfilter_scanlines(data: &[u8], width: u32, height: u32, bits_per_pixel: usize) -> Result<Vec<u8>> {
+  let too_large = || PngError::InvalidImageData { reason: format!("image of {}x{} is too large", width, height) };
+  let len = scanline_len(width, bits_per_pixel).ok_or_else(too_large)?;
+  let expected = filtered_len(width, height, bits_per_pixel).ok_or_else(too_large)?;
+  if data.len() != expected {
+    return Err(PngError::InvalidImageData {
+      reason: format!("image data has {} bytes, expected {}", data.len(), expected),
+    });
+  }
+
+  if expected == 0 {
+    // empty adam7 pass
+    return Ok(vec![]);
+  }
+
+  // filters work on whole bytes, sub-byte pixels use the previous byte
+  let bpp = bits_per_pixel.div_ceil(8);
+  let mut rows = vec![0; len * height as usize];
+  let mut prev = vec![0; len];
+  for (y, (line, row)) in data.chunks_exact(len + 1).zip(rows.chunks_exact_mut(len)).enumerate() {
+    let filter_type = FilterType::try_from(line[0]).map_err(|_| PngError::InvalidImageData {
+      reason: format!("unknown filter type {} in row {}", line[0], y),
+    })?;
+    row.copy_from_slice(&line[1..]);
+    filter::unfilter(filter_type, bpp, &prev, row);
+    prev.copy_from_slice(row);
+  }
+  Ok(rows)
+}
+
+/// Turns packed scanlines into the `ImageBuffer` layout. Only bit depths
+/// below 8 need work, those get one byte per sample.
+pub fn unpack_samples(rows: Vec<u8>, ihdr: &Ihdr, width: u32, height: u32) -> Vec<u8> {
+  let depth = ihdr.bit_depth as usize;
+  if depth >= 8 || width == 0 {
+    return rows;
+  }
+
+  let samples = width as usize * ihdr.color_type.channels() as usize;
+  let len = scanline_len(width, ihdr.bits_per_pixel()).unwrap_or(0);
+  let mask = (1u8 << depth) - 1;
+  let mut pixels = Vec::with_capacity(samples * height as usize);
+  for row in rows.chunks_exact(len) {
+    for i in 0..samples {
+      let bit = i * depth;
+      let shift = 8 - depth - bit % 8;
+      pixels.push((row[bit / 8] >> shift) & mask);
+    }
+  }
+  pixels
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::chunk::Chunk;
+  use crate::chunk_type::ChunkType;
+  use crate::ihdr::ColorType;
+  use crate::png::tests::PNG_FILE;
+  use crate::position::Position;
+  use crate::zlib;
+  use std::str::FromStr;
+
+  /// Builds a png from an IHDR and already filtered scanlines.
+  fn png_from_scanlines(ihdr: Ihdr, scanlines: &[u8]) -> Png {
+    Png::from_chunks(vec![
+      ihdr.to_chunk(),
+      Chunk::new(ChunkType::from_str("IDAT").unwrap(), zlib::deflate(scanlines, 6)),
+      Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+    ])
+  }
+
+  #[test]
+  fn test_decode_image_file() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    let image = decode(&png).unwrap();
+    assert_eq!((image.width(), image.height()), (50, 50));
+    assert_eq!(image.color_type(), ColorType::Rgba);
+    assert_eq!(image.data().len(), 50 * 50 * 4);
+  }
+
+  #[test]
+  fn test_decode_all_filters() {
+    // 2x5 rgb, one row per filter type, every row decodes to the same pixels
+    let ihdr = Ihdr::new(2, 5, 8, ColorType::Rgb).unwrap();
+    #[rustfmt::skip]
+    let scanlines = [
+      0, 10, 20, 30, 40, 50, 60,
+      1, 10, 20, 30, 30, 30, 30,
+      2, 0, 0, 0, 0, 0, 0,
+      3, 5, 10, 15, 15, 15, 15,
+      4, 0, 0, 0, 0, 0, 0,
+    ];
+    let image = decode(&png_from_scanlines(ihdr, &scanlines)).unwrap();
+    for y in 0..5 {
+      assert_eq!(image.row(y), &[10, 20, 30, 40, 50, 60]);
+    }
+  }
+
+  #[test]
+  fn test_decode_sub_byte_depths() {
+    // 5 pixels of 2-bit gray: 0 1 2 3 1 -> 0b00011011 0b01000000
+    let ihdr = Ihdr::new(5, 1, 2, ColorType::Grayscale).unwrap();
+    let image = decode(&png_from_scanlines(ihdr, &[0, 0b0001_1011, 0b0100_0000])).unwrap();
+    assert_eq!(image.data(), &[0, 1, 2, 3, 1]);
+
+    let ihdr = Ihdr::new(3, 2, 1, ColorType::Indexed).unwrap();
+    let image = decode(&png_from_scanlines(ihdr, &[0, 0b1010_0000, 0, 0b0110_0000])).unwrap();
+    assert_eq!(image.data(), &[1, 0, 1, 0, 1, 1]);
+
+    let ihdr = Ihdr::new(3, 1, 4, ColorType::Grayscale).unwrap();
+    let image = decode(&png_from_scanlines(ihdr, &[0, 0xab, 0xc0])).unwrap();
+    assert_eq!(image.data(), &[0xa, 0xb, 0xc]);
+  }
+
+  #[test]
+  fn test_decode_16_bit() {
+    let ihdr = Ihdr::new(1, 2, 16, ColorType::GrayscaleAlpha).unwrap();
+    // second row uses up, adding the previous row byte by byte
+    let image = decode(&png_from_scanlines(ihdr, &[0, 1, 2, 3, 4, 2, 1, 1, 1, 1])).unwrap();
+    assert_eq!(image.sample(0, 0, 0), 0x0102);
+    assert_eq!(image.sample(0, 1, 1), 0x0405);
+  }
+
+  #[test]
+  fn test_decode_interlaced() {
+    // 3x3 gray with the values 1 to 9, passes 2 and 3 are empty
+    let mut ihdr = Ihdr::new(3, 3, 8, ColorType::Grayscale).unwrap();
+    ihdr.interlace_method = 1;
+    #[rustfmt::skip]
+    let scanlines = [
+      0, 1,        // pass 1: (0, 0)
+      0, 3,        // pass 4: (2, 0)
+      0, 7, 9,     // pass 5: (0, 2) (2, 2)
+      0, 2, 0, 8,  // pass 6: (1, 0), (1, 2)
+      0, 4, 5, 6,  // pass 7: row 1
+    ];
+    let png = png_from_scanlines(ihdr, &scanlines);
+    let image = decode(&png).unwrap();
+    assert_eq!(image.data(), &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    assert_eq!(decode_passes(&png).unwrap().len(), 7);
+
+    // the last pass is missing, the others are still there for a preview
+    let passes = adam7::decode_passes(&scanlines[..11], &ihdr).unwrap();
+    assert_eq!(passes.len(), 6);
+    assert_eq!(passes[4].data(), &[7, 9]);
+    assert!(decode(&png_from_scanlines(ihdr, &scanlines[..11])).is_err());
+  }
+
+  #[test]
+  fn test_decode_rgba() {
+    let ihdr = Ihdr::new(3, 1, 2, ColorType::Indexed).unwrap();
+    // indices 0 1 2
+    let mut png = png_from_scanlines(ihdr, &[0, 0b0001_1000]);
+    png.insert_chunk_at(Palette { entries: vec![[1, 2, 3], [4, 5, 6], [7, 8, 9]] }.to_chunk(), Position::BeforeFirstIdat).unwrap();
+    png.insert_chunk_at(Transparency::Indexed(vec![0, 128]).to_chunk(), Position::BeforeFirstIdat).unwrap();
+    let image = decode_rgba(&png).unwrap();
+    assert_eq!(image.color_type(), ColorType::Rgba);
+    assert_eq!(image.data(), &[1, 2, 3, 0, 4, 5, 6, 128, 7, 8, 9, 255]);
+
+    let short = Palette { entries: vec![[0; 3]; 2] };
+    assert!(expand_palette(&decode(&png).unwrap(), &short, None).is_err());
+    // no PLTE at all
+    let png = png_from_scanlines(ihdr, &[0, 0]);
+    assert!(matches!(decode_rgba(&png), Err(PngError::ChunkNotFound { .. })));
+  }
+
+  #[test]
+  fn test_decode_errors() {
+    let ihdr = Ihdr::new(2, 2, 8, ColorType::Grayscale).unwrap();
+    let result = decode(&png_from_scanlines(ihdr, &[0, 1, 2, 5, 1, 2]));
+    assert!(matches!(result, Err(PngError::InvalidImageData { .. })));
+
+    let result = decode(&png_from_scanlines(ihdr, &[0, 1, 2, 0, 1]));
+    assert!(matches!(result, Err(PngError::InvalidImageData { .. })));
+
+    // data after the last scanline
+    assert!(unfilter_scanlines(&[0, 1, 2, 0, 1, 2, 0], 2, 2, 8).is_err());
+    let result = decode(&png_from_scanlines(ihdr, &[0, 1, 2, 0, 1, 2, 0]));
+    assert_eq!(result.unwrap_err().to_string(), "invalid image data: image data inflates to more than the 6 bytes the header asks for");
+  }
+
+  #[test]
+  fn test_decompression_bomb() {
+    // a few kilobytes that would inflate to 20 MB, inflating stops at the 6 bytes the header asks for
+    let ihdr = Ihdr::new(2, 2, 8, ColorType::Grayscale).unwrap();
+    let png = png_from_scanlines(ihdr, &vec![0; 20 << 20]);
+    assert!(png.chunk_by_type("IDAT").unwrap().length() < 50_000);
+    assert!(matches!(png.image_data(), Err(PngError::InvalidImageData { .. })));
+    assert!(matches!(decode(&png), Err(PngError::InvalidImageData { .. })));
+  }
+}
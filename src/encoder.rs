@@ -0,0 +1,279 @@
+use std::str::FromStr;
+
+use crate::adam7;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::decoder::scanline_len;
+use crate::filter::{self, FilterType};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::image::ImageBuffer;
+use crate::palette::Palette;
+use crate::png::Png;
+use crate::{zlib, PngError, Result};
+
+/// How the encoder picks the filter of each scanline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterStrategy {
+  /// Use the same filter for every scanline.
+  Fixed(FilterType),
+  /// Try all filters on every scanline and keep the one with the smallest
+  /// sum of absolute differences, as the spec recommends. Indexed and
+  /// sub-byte images always use no filter.
+  Adaptive,
+}
+
+/// Turns an `ImageBuffer` into a complete png: IHDR, PLTE if there is a
+/// palette, the compressed image data split into IDAT chunks, and IEND.
+#[derive(Debug, Clone)]
+pub struct Encoder {
+  /// Maximum size of the data of one IDAT chunk, clamped to 1..=2^31-1.
+  pub idat_size: usize,
+  /// Zlib compression level from 0 (none) to 9 (best).
+  pub compression: u32,
+  pub filter: FilterStrategy,
+  /// Write the image Adam7 interlaced.
+  pub interlace: bool,
+  /// Written as PLTE, required for indexed images and optional for rgb.
+  pub palette: Option<Palette>,
+}
+
+impl Default for Encoder {
+  fn default() -> Self {
+    Encoder {
+      idat_size: 8192,
+      compression: 6,
+      filter: FilterStrategy::Adaptive,
+      interlace: false,
+      palette: None,
+    }
+  }
+}
+
+impl Encoder {
+  /// Encodes the image into a png. Indexed images need a palette with an
+  /// entry for every index they use.
+  pub fn encode(&self, image: &ImageBuffer) -> Result<Png> {
+    let mut ihdr = Ihdr::new(image.width(), image.height(), image.bit_depth(), image.color_type())?;
+    match &self.palette {
+      Some(palette) => {
+        palette.validate(&ihdr)?;
+        if image.color_type() == ColorType::Indexed {
+          if let Some(&index) = image.data().iter().find(|&&index| index as usize >= palette.entries.len()) {
+            return Err(PngError::InvalidImageData {
+              reason: format!("palette index {} is out of range, the palette has {} entries", index, palette.entries.len()),
+            });
+          }
+        }
+      },
+      None if image.color_type() == ColorType::Indexed =>
+        return Err(PngError::InvalidImageData { reason: "indexed images need a palette".to_string() }),
+      None => {},
+    }
+    let filtered = if self.interlace {
+      ihdr.interlace_method = 1;
+      let mut filtered = Vec::new();
+      for pass in adam7::interlace(image) {
+        let pass_ihdr = Ihdr { width: pass.width(), height: pass.height(), ..ihdr };
+        filtered.extend(self.filter_scanlines(&pack_samples(&pass), &pass_ihdr)?);
+      }
+      filtered
+    } else {
+      self.filter_scanlines(&pack_samples(image), &ihdr)?
+    };
+    let compressed = zlib::deflate(&filtered, self.compression);
+
+    let idat = ChunkType::from_str("IDAT").unwrap();
+    let mut chunks = vec![ihdr.to_chunk()];
+    chunks.extend(self.palette.as_ref().map(Palette::to_chunk));
+    for data in compressed.chunks(self.idat_size.clamp(1, Chunk::MAX_LENGTH as usize)) {
+      chunks.push(Chunk::new(idat.clone(), data.to_vec()));
+    }
+    chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]));
+    Ok(Png::from_chunks(chunks))
+  }
+
+  /// Filters packed scanlines, the result starts every scanline with its
+  /// filter type byte.
+  pub fn filter_scanlines(&self, rows: &[u8], ihdr: &Ihdr) -> Result<Vec<u8>> {
+    let len = scanline_len(ihdr.width, ihdr.bits_per_pixel()).ok_or_else(|| PngError::InvalidImageData {
+      reason: format!("image of {}x{} is too large", ihdr.width, ihdr.height),
+    })?;
+    if len == 0 || ihdr.height == 0 {
+      return Ok(vec![]);
+    }
+
+    let bpp = ihdr.bits_per_pixel().div_ceil(8);
+    let strategy = match self.filter {
+      FilterStrategy::Adaptive if ihdr.color_type == ColorType::Indexed || ihdr.bit_depth < 8 =>
+        FilterStrategy::Fixed(FilterType::None),
+      strategy => strategy,
+    };
+
+    let mut out = Vec::with_capacity(rows.len() + rows.len() / len);
+    let mut prev = vec![0; len];
+    let mut candidate = vec![0; len];
+    let mut best = vec![0; len];
+    for row in rows.chunks_exact(len) {
+      let filter_type = match strategy {
+        FilterStrategy::Fixed(filter_type) => {
+          filter::filter(filter_type, bpp, &prev, row, &mut best);
+          filter_type
+        },
+        FilterStrategy::Adaptive => {
+          let mut best_type = FilterType::None;
+          let mut best_sum = u64::MAX;
+          for filter_type in FilterType::ALL {
+            filter::filter(filter_type, bpp, &prev, row, &mut candidate);
+            // bytes count as signed, so small negative differences are cheap too
+            let sum = candidate.iter().map(|&b| (b as i8).unsigned_abs() as u64).sum();
+            if sum < best_sum {
+              best_sum = sum;
+              best_type = filter_type;
+              std::mem::swap(&mut best, &mut candidate);
+            }
+          }
+          best_type
+        },
+      };
+      out.push(filter_type as u8);
+      out.extend_from_slice(&best);
+      prev.copy_from_slice(row);
+    }
+    Ok(out)
+  }
+}
+
+/// Packs samples from the `ImageBuffer` layout into scanlines. Only bit
+/// depths below 8 need work, those share bytes, most significant bits first.
+pub fn pack_samples(image: &ImageBuffer) -> Vec<u8> {
+  let depth = image.bit_depth() as usize;
+  if depth >= 8 {
+    return image.data().to_vec();
+  }
+
+  let samples = image.width() as usize * image.color_type().channels() as usize;
+  let len = scanline_len(image.width(), depth * image.color_type().channels() as usize).unwrap_or(0);
+  let mut rows = vec![0; len * image.height() as usize];
+  if samples == 0 {
+    return rows;
+  }
+  for (row, packed) in image.data().chunks_exact(samples).zip(rows.chunks_exact_mut(len)) {
+    for (i, &sample) in row.iter().enumerate() {
+      let bit = i * depth;
+      packed[bit / 8] |= sample << (8 - depth - bit % 8);
+    }
+  }
+  rows
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::validate;
+
+  /// Deterministic noise so the tests don't need a random number crate.
+  fn noise(len: usize, bit_depth: u8) -> Vec<u8> {
+    let mut state: u32 = 0x1234_5678;
+    (0..len).map(|_| {
+      state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+      let byte = (state >> 16) as u8;
+      if bit_depth < 8 { byte >> (8 - bit_depth) } else { byte }
+    }).collect()
+  }
+
+  fn testing_image(width: u32, height: u32, color_type: ColorType, bit_depth: u8) -> ImageBuffer {
+    let bytes_per_sample = if bit_depth == 16 { 2 } else { 1 };
+    let len = (width * height) as usize * color_type.channels() as usize * bytes_per_sample;
+    ImageBuffer::new(width, height, color_type, bit_depth, noise(len, bit_depth)).unwrap()
+  }
+
+  /// The default encoder, with a gray ramp palette for indexed images.
+  fn encoder_for(image: &ImageBuffer) -> Encoder {
+    let palette = (image.color_type() == ColorType::Indexed).then(|| Palette {
+      entries: (0..1u16 << image.bit_depth()).map(|i| [i as u8; 3]).collect(),
+    });
+    Encoder { palette, ..Encoder::default() }
+  }
+
+  #[test]
+  fn test_round_trip_all_formats() {
+    for color_type in [ColorType::Grayscale, ColorType::Rgb, ColorType::Indexed, ColorType::GrayscaleAlpha, ColorType::Rgba] {
+      for &bit_depth in color_type.allowed_bit_depths() {
+        let image = testing_image(13, 7, color_type, bit_depth);
+        let png = encoder_for(&image).encode(&image).unwrap();
+        let bytes = png.as_bytes();
+        assert!(validate::check_bytes(&bytes).is_empty(), "{} at {} bits", color_type, bit_depth);
+        let decoded = Png::try_from(bytes.as_slice()).unwrap().decode().unwrap();
+        assert_eq!(decoded, image, "{} at {} bits", color_type, bit_depth);
+      }
+    }
+  }
+
+  #[test]
+  fn test_round_trip_fixed_filters() {
+    let image = testing_image(9, 9, ColorType::Rgba, 16);
+    for filter_type in FilterType::ALL {
+      let encoder = Encoder { filter: FilterStrategy::Fixed(filter_type), ..Encoder::default() };
+      let png = encoder.encode(&image).unwrap();
+      assert_eq!(png.decode().unwrap(), image);
+    }
+  }
+
+  #[test]
+  fn test_round_trip_interlaced() {
+    let encoder = Encoder { interlace: true, ..Encoder::default() };
+    for (width, height) in [(1, 1), (3, 2), (8, 8), (13, 7)] {
+      for (color_type, bit_depth) in [(ColorType::Grayscale, 1), (ColorType::Indexed, 4), (ColorType::Rgba, 16)] {
+        let image = testing_image(width, height, color_type, bit_depth);
+        let png = Encoder { palette: encoder_for(&image).palette, ..encoder.clone() }.encode(&image).unwrap();
+        assert!(validate::check_bytes(&png.as_bytes()).is_empty());
+        assert!(png.header_info().unwrap().is_interlaced());
+        assert_eq!(png.decode().unwrap(), image);
+        assert_eq!(png.decode_passes().unwrap(), adam7::interlace(&image));
+      }
+    }
+  }
+
+  #[test]
+  fn test_palette() {
+    let image = ImageBuffer::new(3, 1, ColorType::Indexed, 2, vec![0, 1, 2]).unwrap();
+    assert!(Encoder::default().encode(&image).is_err());
+    let palette = Palette { entries: vec![[255, 0, 0], [0, 255, 0], [0, 0, 255]] };
+    let encoder = Encoder { palette: Some(palette.clone()), ..Encoder::default() };
+    let png = encoder.encode(&image).unwrap();
+    assert!(png.validate().is_empty());
+    assert_eq!(png.palette().unwrap(), Some(palette.clone()));
+    assert_eq!(png.decode_rgba().unwrap().data(), &[255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255]);
+
+    let out_of_range = ImageBuffer::new(1, 1, ColorType::Indexed, 2, vec![3]).unwrap();
+    assert!(encoder.encode(&out_of_range).is_err());
+    // a suggested palette for rgb is fine, grayscale can't have one
+    assert!(encoder.encode(&testing_image(2, 2, ColorType::Rgb, 8)).unwrap().validate().is_empty());
+    assert!(encoder.encode(&testing_image(2, 2, ColorType::Grayscale, 8)).is_err());
+  }
+
+  #[test]
+  fn test_idat_split() {
+    let image = testing_image(64, 64, ColorType::Rgb, 8);
+    let encoder = Encoder { idat_size: 100, compression: 0, ..Encoder::default() };
+    let png = encoder.encode(&image).unwrap();
+
+    let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+    assert_eq!(types.first().unwrap(), "IHDR");
+    assert_eq!(types.last().unwrap(), "IEND");
+    let idats = &png.chunks()[1..png.chunks().len() - 1];
+    assert!(idats.len() > 1);
+    assert!(idats.iter().all(|c| c.chunk_type().to_string() == "IDAT" && c.length() <= 100));
+    assert_eq!(png.decode().unwrap(), image);
+  }
+
+  #[test]
+  fn test_adaptive_filter_prefers_gradients() {
+    // a horizontal gradient compresses best with sub
+    let data: Vec<u8> = (0..4).flat_map(|_| 0..16u8).collect();
+    let image = ImageBuffer::new(16, 4, ColorType::Grayscale, 8, data).unwrap();
+    let ihdr = Ihdr::new(16, 4, 8, ColorType::Grayscale).unwrap();
+    let filtered = Encoder::default().filter_scanlines(&pack_samples(&image), &ihdr).unwrap();
+    assert_eq!(filtered[0], FilterType::Sub as u8);
+  }
+}
@@ -0,0 +1,169 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+use crate::chunk::array4_from_slice;
+use crate::{PngError, Result};
+
+/// Marks data as encrypted by `seal`.
+pub const MAGIC: [u8; 4] = [0x89, b'E', b'N', b'C'];
+
+/// Layout of the header, the only version so far:
+/// magic, version, kdf id, cipher id, a zero byte, the argon2 memory,
+/// iterations and parallelism as u32, then the salt and the nonce. The
+/// ciphertext and its tag follow, the header is authenticated with it.
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 20 + SALT_LEN + NONCE_LEN;
+
+/// Argon2id, version 0x13.
+pub const KDF_ARGON2ID: u8 = 1;
+/// XChaCha20-Poly1305, its nonces are long enough to be random.
+pub const CIPHER_XCHACHA20POLY1305: u8 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+
+/// Cost of deriving the key from the passphrase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+  pub memory_kib: u32,
+  pub iterations: u32,
+  pub parallelism: u32,
+}
+
+impl KdfParams {
+  /// Limits for params read from a file, so a hostile file can't make
+  /// opening it take all memory or forever.
+  pub const MAX: KdfParams = KdfParams { memory_kib: 1 << 20, iterations: 16, parallelism: 16 };
+
+  fn check(&self) -> Result<Params> {
+    if self.memory_kib > Self::MAX.memory_kib || self.iterations > Self::MAX.iterations || self.parallelism > Self::MAX.parallelism {
+      return Err(invalid(format!("key derivation cost {:?} is above the limit {:?}", self, Self::MAX)));
+    }
+    Params::new(self.memory_kib, self.iterations, self.parallelism, Some(KEY_LEN))
+      .map_err(|err| invalid(format!("key derivation params {:?}: {}", self, err)))
+  }
+}
+
+/// The recommended minimum for argon2id: 19 MiB, 2 iterations.
+impl Default for KdfParams {
+  fn default() -> Self {
+    KdfParams { memory_kib: 19 * 1024, iterations: 2, parallelism: 1 }
+  }
+}
+
+/// Whether the data starts like an envelope made by `seal`.
+pub fn is_sealed(data: &[u8]) -> bool {
+  data.starts_with(&MAGIC)
+}
+
+/// Encrypts data with a key derived from the passphrase, see `seal_with`.
+pub fn seal(data: &[u8], passphrase: &[u8]) -> Result<Vec<u8>> {
+  seal_with(data, passphrase, &KdfParams::default())
+}
+
+/// Encrypts data with a key derived from the passphrase with argon2id and
+/// a random salt. The result holds everything `open` needs besides the
+/// passphrase.
+pub fn seal_with(data: &[u8], passphrase: &[u8], params: &KdfParams) -> Result<Vec<u8>> {
+  let mut salt = [0; SALT_LEN];
+  let mut nonce = [0; NONCE_LEN];
+  getrandom::getrandom(&mut salt).and_then(|_| getrandom::getrandom(&mut nonce))
+    .map_err(|err| PngError::Io(std::io::Error::other(err.to_string())))?;
+
+  let mut header = Vec::with_capacity(HEADER_LEN);
+  header.extend_from_slice(&MAGIC);
+  header.extend_from_slice(&[VERSION, KDF_ARGON2ID, CIPHER_XCHACHA20POLY1305, 0]);
+  header.extend_from_slice(&params.memory_kib.to_be_bytes());
+  header.extend_from_slice(&params.iterations.to_be_bytes());
+  header.extend_from_slice(&params.parallelism.to_be_bytes());
+  header.extend_from_slice(&salt);
+  header.extend_from_slice(&nonce);
+
+  let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &salt, params)?.into());
+  let sealed = cipher.encrypt(XNonce::from_slice(&nonce), Payload { msg: data, aad: &header })
+    .map_err(|_| invalid("encryption failed".to_string()))?;
+  header.extend(sealed);
+  Ok(header)
+}
+
+/// Decrypts an envelope made by `seal`. A wrong passphrase, or data that
+/// was changed, gives `AuthenticationFailed`.
+pub fn open(envelope: &[u8], passphrase: &[u8]) -> Result<Vec<u8>> {
+  if !is_sealed(envelope) {
+    return Err(invalid("the data is not encrypted".to_string()));
+  }
+  if envelope.len() < HEADER_LEN {
+    return Err(invalid(format!("{} bytes is too short for the header", envelope.len())));
+  }
+  let (header, sealed) = envelope.split_at(HEADER_LEN);
+  match (header[4], header[5], header[6]) {
+    (VERSION, KDF_ARGON2ID, CIPHER_XCHACHA20POLY1305) => {},
+    (VERSION, kdf, cipher) => return Err(invalid(format!("unknown key derivation {} or cipher {}", kdf, cipher))),
+    (version, _, _) => return Err(invalid(format!("unknown version {}", version))),
+  }
+  let field = |i: usize| u32::from_be_bytes(array4_from_slice(&header[i..i + 4]));
+  let params = KdfParams { memory_kib: field(8), iterations: field(12), parallelism: field(16) };
+  let salt = &header[20..20 + SALT_LEN];
+  let nonce = &header[20 + SALT_LEN..];
+
+  let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, salt, &params)?.into());
+  cipher.decrypt(XNonce::from_slice(nonce), Payload { msg: sealed, aad: header })
+    .map_err(|_| PngError::AuthenticationFailed)
+}
+
+fn derive_key(passphrase: &[u8], salt: &[u8], params: &KdfParams) -> Result<[u8; KEY_LEN]> {
+  let mut key = [0; KEY_LEN];
+  Argon2::new(Algorithm::Argon2id, Version::V0x13, params.check()?)
+    .hash_password_into(passphrase, salt, &mut key)
+    .map_err(|err| invalid(format!("key derivation failed: {}", err)))?;
+  Ok(key)
+}
+
+fn invalid(reason: String) -> PngError {
+  PngError::InvalidEnvelope { reason }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // cheap params, the defaults are slow in debug builds
+  const PARAMS: KdfParams = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+
+  #[test]
+  fn test_seal_and_open() {
+    let sealed = seal_with(b"hidden message", b"secret", &PARAMS).unwrap();
+    assert!(is_sealed(&sealed));
+    assert_eq!(sealed.len(), HEADER_LEN + 14 + 16);
+    assert_eq!(open(&sealed, b"secret").unwrap(), b"hidden message");
+    // salt and nonce are random
+    assert_ne!(seal_with(b"hidden message", b"secret", &PARAMS).unwrap(), sealed);
+  }
+
+  #[test]
+  fn test_wrong_password_or_changed_data() {
+    let sealed = seal_with(b"hidden message", b"secret", &PARAMS).unwrap();
+    assert!(matches!(open(&sealed, b"guess"), Err(PngError::AuthenticationFailed)));
+    for i in [11, 30, HEADER_LEN + 3, sealed.len() - 1] {
+      let mut changed = sealed.clone();
+      changed[i] ^= 1;
+      assert!(matches!(open(&changed, b"secret"), Err(PngError::AuthenticationFailed)));
+    }
+  }
+
+  #[test]
+  fn test_invalid_envelope() {
+    let sealed = seal_with(b"hidden message", b"secret", &PARAMS).unwrap();
+    assert!(matches!(open(b"plain text", b"secret"), Err(PngError::InvalidEnvelope { .. })));
+    assert!(matches!(open(&sealed[..HEADER_LEN - 1], b"secret"), Err(PngError::InvalidEnvelope { .. })));
+    let mut changed = sealed.clone();
+    changed[4] = 2;
+    assert_eq!(open(&changed, b"secret").unwrap_err().to_string(), "invalid encrypted data: unknown version 2");
+    // a hostile memory cost is refused before anything is allocated
+    changed = sealed;
+    changed[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(matches!(open(&changed, b"secret"), Err(PngError::InvalidEnvelope { .. })));
+  }
+}
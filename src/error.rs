@@ -0,0 +1,169 @@
+use std::fmt;
+
+/// Everything that can go wrong while reading, building or writing a png.
+/// Variants that come out of parsing carry the chunk index (0-based, not
+/// counting the signature) and the byte offset of the chunk's length field
+/// relative to the start of the parsed input.
+#[derive(Debug)]
+pub enum PngError {
+  /// The first 8 bytes are not the png signature.
+  InvalidSignature { found: Vec<u8> },
+  /// The input ends before the chunk is complete.
+  TruncatedChunk { index: usize, offset: usize, needed: usize, available: usize },
+  /// The length field exceeds the maximum chunk length of 2^31 - 1.
+  ChunkTooLong { index: usize, offset: usize, length: u32 },
+  /// The crc stored in the chunk (`expected`) does not match the crc
+  /// computed over its type and data (`actual`).
+  CrcMismatch { index: usize, offset: usize, expected: u32, actual: u32 },
+  /// The chunk type is not 4 ascii letters. Offset and index are only
+  /// known when the type was read from a file.
+  InvalidChunkType { bytes: Vec<u8>, index: Option<usize>, offset: Option<usize> },
+  /// The chunk data doesn't follow the spec for its type.
+  InvalidChunkData { chunk_type: String, reason: String },
+  /// The chunk can't go at this position, `reason` says which rule it breaks.
+  InvalidChunkOrder { index: usize, chunk_type: String, reason: String },
+  /// The chunk at `index` breaks a rule of the spec, found by validating
+  /// a whole png.
+  StructureViolation { index: usize, chunk_type: String, reason: String },
+  /// No chunk with the given type exists.
+  ChunkNotFound { chunk_type: String },
+  /// There are chunks with the type, but only `count` of them, so none has
+  /// the index.
+  ChunkIndexOutOfRange { chunk_type: String, index: usize, count: usize },
+  /// No tEXt, zTXt or iTXt chunk has the keyword.
+  KeywordNotFound { keyword: String },
+  /// Removing the chunk would break the image, it is one of IHDR, PLTE,
+  /// IDAT and IEND. `reason` says whether it can be forced.
+  CriticalChunk { chunk_type: String, reason: String },
+  /// A chunk position that can't be parsed or points past the last chunk.
+  InvalidPosition { position: String, reason: String },
+  /// The chunk data is not valid utf8, `valid_up_to` is the offset of the
+  /// first bad byte in the data.
+  InvalidUtf8 { chunk_type: String, valid_up_to: usize },
+  /// The compressed image data is broken. `index` is the chunk that was
+  /// being read, `stream_offset` the offset in the joined zlib stream.
+  Zlib { index: usize, stream_offset: usize, reason: String },
+  /// Compressed data inflates to more than `limit` bytes.
+  InflateLimit { limit: usize },
+  /// The inflated image data doesn't match the header, or pixels don't
+  /// match their color type and bit depth.
+  InvalidImageData { reason: String },
+  /// Arguments or data given on the command line don't fit, e.g. hex with
+  /// a letter that is not a hex digit.
+  InvalidInput { reason: String },
+  /// Encrypted data that can't be opened: not encrypted, cut short, or of
+  /// an unknown version or algorithm.
+  InvalidEnvelope { reason: String },
+  /// Decrypting failed, the password is wrong or the data was changed.
+  AuthenticationFailed,
+  /// The chunks break the structure rules of the spec, see
+  /// `Png::validate`.
+  InvalidStructure { violations: Vec<PngError> },
+  /// Reading or writing failed.
+  Io(std::io::Error),
+}
+
+impl PngError {
+  /// Invalid chunk type that didn't come from a file, e.g. a cli argument.
+  pub fn invalid_chunk_type(bytes: &[u8]) -> PngError {
+    PngError::InvalidChunkType { bytes: bytes.to_vec(), index: None, offset: None }
+  }
+
+  /// Short name of the variant, stable enough for scripts to match on.
+  pub fn kind(&self) -> &'static str {
+    match self {
+      PngError::InvalidSignature { .. } => "invalid_signature",
+      PngError::TruncatedChunk { .. } => "truncated_chunk",
+      PngError::ChunkTooLong { .. } => "chunk_too_long",
+      PngError::CrcMismatch { .. } => "crc_mismatch",
+      PngError::InvalidChunkType { .. } => "invalid_chunk_type",
+      PngError::InvalidChunkData { .. } => "invalid_chunk_data",
+      PngError::InvalidChunkOrder { .. } => "invalid_chunk_order",
+      PngError::StructureViolation { .. } => "structure_violation",
+      PngError::ChunkNotFound { .. } => "chunk_not_found",
+      PngError::ChunkIndexOutOfRange { .. } => "chunk_index_out_of_range",
+      PngError::KeywordNotFound { .. } => "keyword_not_found",
+      PngError::CriticalChunk { .. } => "critical_chunk",
+      PngError::InvalidPosition { .. } => "invalid_position",
+      PngError::InvalidUtf8 { .. } => "invalid_utf8",
+      PngError::Zlib { .. } => "zlib",
+      PngError::InflateLimit { .. } => "inflate_limit",
+      PngError::InvalidImageData { .. } => "invalid_image_data",
+      PngError::InvalidInput { .. } => "invalid_input",
+      PngError::InvalidEnvelope { .. } => "invalid_envelope",
+      PngError::AuthenticationFailed => "authentication_failed",
+      PngError::InvalidStructure { .. } => "invalid_structure",
+      PngError::Io(_) => "io",
+    }
+  }
+}
+
+impl fmt::Display for PngError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PngError::InvalidSignature { found } =>
+        write!(f, "invalid png signature {:?}", found),
+      PngError::TruncatedChunk { index, offset, needed, available } =>
+        write!(f, "chunk {} at offset {} is truncated: needs {} bytes, {} available", index, offset, needed, available),
+      PngError::ChunkTooLong { index, offset, length } =>
+        write!(f, "chunk {} at offset {} has length {}, the maximum is {}", index, offset, length, (1u32 << 31) - 1),
+      PngError::CrcMismatch { index, offset, expected, actual } =>
+        write!(f, "crc mismatch in chunk {} at offset {}: expected {:#010x}, got {:#010x}", index, offset, expected, actual),
+      PngError::InvalidChunkType { bytes, index: Some(index), offset: Some(offset) } =>
+        write!(f, "invalid chunk type {:?} in chunk {} at offset {}", bytes, index, offset),
+      PngError::InvalidChunkType { bytes, .. } =>
+        write!(f, "invalid chunk type {:?}", String::from_utf8_lossy(bytes)),
+      PngError::InvalidChunkData { chunk_type, reason } =>
+        write!(f, "invalid {} chunk: {}", chunk_type, reason),
+      PngError::InvalidChunkOrder { index, chunk_type, reason } =>
+        write!(f, "can't put {} at chunk {}: {}", chunk_type, index, reason),
+      PngError::StructureViolation { index, chunk_type, reason } =>
+        write!(f, "{} at chunk {} {}", chunk_type, index, reason),
+      PngError::ChunkNotFound { chunk_type } =>
+        write!(f, "no chunk with type {}", chunk_type),
+      PngError::ChunkIndexOutOfRange { chunk_type, index, count } =>
+        write!(f, "no {} chunk at index {}, there {} only {}", chunk_type, index, if *count == 1 { "is" } else { "are" }, count),
+      PngError::KeywordNotFound { keyword } =>
+        write!(f, "no text with keyword {:?}", keyword),
+      PngError::CriticalChunk { chunk_type, reason } =>
+        write!(f, "can't remove {}: {}", chunk_type, reason),
+      PngError::InvalidPosition { position, reason } =>
+        write!(f, "invalid position {:?}: {}", position, reason),
+      PngError::InvalidUtf8 { chunk_type, valid_up_to } =>
+        write!(f, "data of chunk {} is not valid utf8 after byte {}", chunk_type, valid_up_to),
+      PngError::Zlib { index, stream_offset, reason } =>
+        write!(f, "zlib error in chunk {} at stream offset {}: {}", index, stream_offset, reason),
+      PngError::InflateLimit { limit } =>
+        write!(f, "compressed data inflates to more than {} bytes", limit),
+      PngError::InvalidImageData { reason } =>
+        write!(f, "invalid image data: {}", reason),
+      PngError::InvalidInput { reason } =>
+        write!(f, "invalid input: {}", reason),
+      PngError::InvalidEnvelope { reason } =>
+        write!(f, "invalid encrypted data: {}", reason),
+      PngError::AuthenticationFailed =>
+        write!(f, "decryption failed: wrong password or the data was changed"),
+      PngError::InvalidStructure { violations } => {
+        write!(f, "the png breaks {} structure rules", violations.len())?;
+        violations.iter().try_for_each(|violation| write!(f, "\n  {}", violation))
+      },
+      PngError::Io(err) =>
+        write!(f, "io error: {}", err),
+    }
+  }
+}
+
+impl std::error::Error for PngError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      PngError::Io(err) => Some(err),
+      _ => None,
+    }
+  }
+}
+
+impl From<std::io::Error> for PngError {
+  fn from(err: std::io::Error) -> Self {
+    PngError::Io(err)
+  }
+}
@@ -0,0 +1,178 @@
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use pngproject::png::Png;
+use pngproject::{PngError, Result};
+
+use crate::args::WriteArgs;
+
+/// Write the png to `destination` without ever leaving a half written file
+/// behind: it goes to a temporary file in the same directory first, which
+/// is then renamed over the destination. With a backup suffix the file
+/// that gets replaced is copied first, its path is returned. A replaced
+/// file keeps its permissions, with preserve the permissions and timestamps
+/// of `source` are copied. A png that breaks the chunk order rules is not
+/// written at all.
+pub fn write_png(png: &Png, source: &Path, destination: &Path, options: &WriteArgs) -> Result<Option<PathBuf>> {
+  let violations = png.validate();
+  if !violations.is_empty() {
+    return Err(PngError::InvalidStructure { violations });
+  }
+  write_png_unchecked(png, source, destination, options)
+}
+
+/// Like `write_png`, but the png is written even if it breaks the chunk
+/// order rules, as long as it starts with IHDR and ends with IEND.
+pub fn write_png_unchecked(png: &Png, source: &Path, destination: &Path, options: &WriteArgs) -> Result<Option<PathBuf>> {
+  let temp = temp_path(destination)?;
+  let result = write_and_rename(png, source, destination, &temp, options);
+  if result.is_err() {
+    let _ = fs::remove_file(&temp);
+  }
+  result
+}
+
+fn write_and_rename(png: &Png, source: &Path, destination: &Path, temp: &Path, options: &WriteArgs) -> Result<Option<PathBuf>> {
+  let file = OpenOptions::new().write(true).create_new(true).open(temp)?;
+  let file = png.write_to(BufWriter::new(file))?.into_inner().map_err(|err| err.into_error())?;
+  if options.preserve {
+    preserve_metadata(source, &file)?;
+  } else if let Ok(metadata) = fs::metadata(destination) {
+    file.set_permissions(metadata.permissions())?;
+  }
+  file.sync_all()?;
+
+  let backup = match &options.backup {
+    Some(suffix) if destination.exists() => {
+      let mut backup = destination.as_os_str().to_owned();
+      backup.push(suffix);
+      fs::copy(destination, &backup)?;
+      let modified = fs::metadata(destination)?.modified()?;
+      File::options().write(true).open(&backup)?.set_modified(modified)?;
+      Some(PathBuf::from(backup))
+    },
+    _ => None,
+  };
+  fs::rename(temp, destination)?;
+  Ok(backup)
+}
+
+/// A hidden file next to the destination, renaming only works within the
+/// same file system.
+fn temp_path(destination: &Path) -> Result<PathBuf> {
+  let name = destination.file_name().ok_or_else(|| PngError::Io(std::io::Error::new(
+    std::io::ErrorKind::InvalidInput,
+    format!("{} is not a file name", destination.display()),
+  )))?;
+  let mut temp_name = std::ffi::OsString::from(".");
+  temp_name.push(name);
+  temp_name.push(format!(".{}.tmp", std::process::id()));
+  Ok(destination.with_file_name(temp_name))
+}
+
+fn preserve_metadata(source: &Path, file: &File) -> Result<()> {
+  let metadata = fs::metadata(source)?;
+  file.set_permissions(metadata.permissions())?;
+  file.set_times(FileTimes::new().set_accessed(metadata.accessed()?).set_modified(metadata.modified()?))?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::{Duration, SystemTime};
+
+  fn testing_png() -> Png {
+    let image = pngproject::image::ImageBuffer::new(1, 1, pngproject::ihdr::ColorType::Grayscale, 8, vec![7]).unwrap();
+    Png::from_image(&image).unwrap()
+  }
+
+  fn testing_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pngproject-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn test_write_with_backup() {
+    let dir = testing_dir("backup");
+    let destination = dir.join("out.png");
+    fs::write(&destination, b"old").unwrap();
+
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    File::options().write(true).open(&destination).unwrap().set_modified(modified).unwrap();
+
+    let options = WriteArgs { in_place: true, backup: Some(".bak".to_string()), preserve: false };
+    let backup = write_png(&testing_png(), &destination, &destination, &options).unwrap();
+    assert_eq!(backup, Some(dir.join("out.png.bak")));
+    assert_eq!(fs::read(dir.join("out.png.bak")).unwrap(), b"old");
+    assert_eq!(fs::metadata(dir.join("out.png.bak")).unwrap().modified().unwrap(), modified);
+    assert_eq!(fs::read(&destination).unwrap(), testing_png().as_bytes());
+    // no temporary files are left behind
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_write_preserves_metadata() {
+    let dir = testing_dir("preserve");
+    let source = dir.join("in.png");
+    fs::write(&source, testing_png().as_bytes()).unwrap();
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    File::options().write(true).open(&source).unwrap()
+      .set_times(FileTimes::new().set_accessed(modified).set_modified(modified)).unwrap();
+
+    let destination = dir.join("out.png");
+    let options = WriteArgs { in_place: false, backup: Some(".bak".to_string()), preserve: true };
+    assert_eq!(write_png(&testing_png(), &source, &destination, &options).unwrap(), None);
+    assert_eq!(fs::metadata(&destination).unwrap().modified().unwrap(), modified);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_overwrite_keeps_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = testing_dir("permissions");
+    let destination = dir.join("out.png");
+    fs::write(&destination, b"old").unwrap();
+    fs::set_permissions(&destination, fs::Permissions::from_mode(0o600)).unwrap();
+
+    let options = WriteArgs { in_place: true, backup: None, preserve: false };
+    write_png(&testing_png(), &destination, &destination, &options).unwrap();
+    assert_eq!(fs::metadata(&destination).unwrap().permissions().mode() & 0o777, 0o600);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_invalid_png_is_not_written() {
+    let dir = testing_dir("invalid");
+    let png = Png::from_chunks(testing_png().chunks()[..1].to_vec());
+    let destination = dir.join("out.png");
+    let options = WriteArgs { in_place: false, backup: None, preserve: false };
+    assert!(matches!(write_png(&png, &destination, &destination, &options), Err(PngError::InvalidStructure { .. })));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_failed_write_cleans_up() {
+    let dir = testing_dir("failed");
+    let options = WriteArgs { in_place: false, backup: None, preserve: false };
+    // the writer refuses a png without IEND after the temporary file is made
+    let png = Png::from_chunks(testing_png().chunks()[..1].to_vec());
+    let destination = dir.join("out.png");
+    assert!(matches!(write_png_unchecked(&png, &destination, &destination, &options), Err(PngError::InvalidChunkOrder { .. })));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+    // renaming over a directory fails after the png is written
+    fs::create_dir(&destination).unwrap();
+    fs::write(destination.join("keep"), b"").unwrap();
+    assert!(matches!(write_png(&testing_png(), &destination, &destination, &options), Err(PngError::Io(_))));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,143 @@
+use crate::{PngError, Result};
+
+/// Filter type byte at the start of every scanline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+  None = 0,
+  Sub = 1,
+  Up = 2,
+  Average = 3,
+  Paeth = 4,
+}
+
+impl FilterType {
+  pub const ALL: [FilterType; 5] = [FilterType::None, FilterType::Sub, FilterType::Up, FilterType::Average, FilterType::Paeth];
+}
+
+impl TryFrom<u8> for FilterType {
+  type Error = PngError;
+
+  fn try_from(value: u8) -> Result<Self> {
+    FilterType::ALL.get(value as usize).copied().ok_or_else(|| PngError::InvalidImageData {
+      reason: format!("unknown filter type {}", value),
+    })
+  }
+}
+
+/// Reverses the filter of one scanline in place. `prev` is the already
+/// unfiltered previous scanline, all zeros for the first one, and `bpp` the
+/// number of bytes per complete pixel, rounded up to 1.
+pub fn unfilter(filter: FilterType, bpp: usize, prev: &[u8], row: &mut [u8]) {
+  match filter {
+    FilterType::None => {},
+    FilterType::Sub => {
+      for i in bpp..row.len() {
+        row[i] = row[i].wrapping_add(row[i - bpp]);
+      }
+    },
+    FilterType::Up => {
+      for (x, &b) in row.iter_mut().zip(prev) {
+        *x = x.wrapping_add(b);
+      }
+    },
+    FilterType::Average => {
+      for i in 0..row.len() {
+        let a = if i >= bpp { row[i - bpp] as u16 } else { 0 };
+        row[i] = row[i].wrapping_add(((a + prev[i] as u16) / 2) as u8);
+      }
+    },
+    FilterType::Paeth => {
+      for i in 0..row.len() {
+        let (a, c) = if i >= bpp { (row[i - bpp], prev[i - bpp]) } else { (0, 0) };
+        row[i] = row[i].wrapping_add(paeth(a, prev[i], c));
+      }
+    },
+  }
+}
+
+/// Applies a filter to one scanline, writing the filtered bytes to `out`.
+/// `prev` is the unfiltered previous scanline, all zeros for the first one.
+pub fn filter(filter: FilterType, bpp: usize, prev: &[u8], row: &[u8], out: &mut [u8]) {
+  for i in 0..row.len() {
+    let (a, c) = if i >= bpp { (row[i - bpp], prev[i - bpp]) } else { (0, 0) };
+    let b = prev[i];
+    let predicted = match filter {
+      FilterType::None => 0,
+      FilterType::Sub => a,
+      FilterType::Up => b,
+      FilterType::Average => ((a as u16 + b as u16) / 2) as u8,
+      FilterType::Paeth => paeth(a, b, c),
+    };
+    out[i] = row[i].wrapping_sub(predicted);
+  }
+}
+
+/// Paeth predictor, picks whichever of left, up and upper left is closest
+/// to left + up - upper left.
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+  let p = a as i16 + b as i16 - c as i16;
+  let pa = (p - a as i16).abs();
+  let pb = (p - b as i16).abs();
+  let pc = (p - c as i16).abs();
+  if pa <= pb && pa <= pc {
+    a
+  } else if pb <= pc {
+    b
+  } else {
+    c
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_unfilter_sub() {
+    let mut row = [1, 2, 3, 4, 5, 6];
+    unfilter(FilterType::Sub, 2, &[0; 6], &mut row);
+    assert_eq!(row, [1, 2, 4, 6, 9, 12]);
+  }
+
+  #[test]
+  fn test_unfilter_up() {
+    let mut row = [1, 2, 255];
+    unfilter(FilterType::Up, 1, &[10, 20, 2], &mut row);
+    assert_eq!(row, [11, 22, 1]);
+  }
+
+  #[test]
+  fn test_unfilter_average() {
+    let mut row = [1, 2, 3];
+    unfilter(FilterType::Average, 1, &[10, 20, 30], &mut row);
+    // 1 + 10/2, 2 + (6 + 20)/2, 3 + (15 + 30)/2
+    assert_eq!(row, [6, 15, 25]);
+  }
+
+  #[test]
+  fn test_unfilter_paeth() {
+    let mut row = [1, 2, 3];
+    unfilter(FilterType::Paeth, 1, &[10, 20, 30], &mut row);
+    // first byte predicts up (10), then a=11 b=20 c=10 -> p=21 picks b=20,
+    // then a=22 b=30 c=20 -> p=32 picks b=30
+    assert_eq!(row, [11, 22, 33]);
+  }
+
+  #[test]
+  fn test_filter_round_trip() {
+    let prev = [3, 200, 17, 255, 0, 42, 99];
+    let row = [250, 1, 64, 128, 7, 7, 180];
+    for filter_type in FilterType::ALL {
+      let mut filtered = [0; 7];
+      filter(filter_type, 3, &prev, &row, &mut filtered);
+      unfilter(filter_type, 3, &prev, &mut filtered);
+      assert_eq!(filtered, row);
+    }
+  }
+
+  #[test]
+  fn test_invalid_filter_type() {
+    assert_eq!(FilterType::try_from(4).unwrap(), FilterType::Paeth);
+    assert!(FilterType::try_from(5).is_err());
+  }
+}
@@ -0,0 +1,226 @@
+use std::str::FromStr;
+
+use crate::chunk::{Chunk, array4_from_slice, check_type, invalid};
+use crate::chunk_type::ChunkType;
+use crate::{Error, Result};
+
+/// Color type from the IHDR chunk, the value is the byte stored in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+  Grayscale = 0,
+  Rgb = 2,
+  Indexed = 3,
+  GrayscaleAlpha = 4,
+  Rgba = 6,
+}
+
+impl ColorType {
+  /// Number of samples per pixel.
+  pub fn channels(&self) -> u8 {
+    match self {
+      ColorType::Grayscale | ColorType::Indexed => 1,
+      ColorType::GrayscaleAlpha => 2,
+      ColorType::Rgb => 3,
+      ColorType::Rgba => 4,
+    }
+  }
+
+  /// Bit depths the spec allows for this color type.
+  pub fn allowed_bit_depths(&self) -> &'static [u8] {
+    match self {
+      ColorType::Grayscale => &[1, 2, 4, 8, 16],
+      ColorType::Indexed => &[1, 2, 4, 8],
+      ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+    }
+  }
+}
+
+impl TryFrom<u8> for ColorType {
+  type Error = Error;
+
+  fn try_from(value: u8) -> Result<Self> {
+    match value {
+      0 => Ok(ColorType::Grayscale),
+      2 => Ok(ColorType::Rgb),
+      3 => Ok(ColorType::Indexed),
+      4 => Ok(ColorType::GrayscaleAlpha),
+      6 => Ok(ColorType::Rgba),
+      _ => Err(invalid("IHDR", format!("unknown color type {}", value))),
+    }
+  }
+}
+
+impl std::fmt::Display for ColorType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let name = match self {
+      ColorType::Grayscale => "grayscale",
+      ColorType::Rgb => "rgb",
+      ColorType::Indexed => "indexed",
+      ColorType::GrayscaleAlpha => "grayscale+alpha",
+      ColorType::Rgba => "rgba",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// The image header, always the first chunk of a png.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+  pub width: u32,
+  pub height: u32,
+  pub bit_depth: u8,
+  pub color_type: ColorType,
+  pub compression_method: u8,
+  pub filter_method: u8,
+  pub interlace_method: u8,
+}
+
+impl Ihdr {
+  /// Length of the IHDR chunk data.
+  pub const LENGTH: usize = 13;
+
+  /// Creates a non-interlaced header and checks it against the spec.
+  pub fn new(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Result<Ihdr> {
+    let ihdr = Ihdr {
+      width,
+      height,
+      bit_depth,
+      color_type,
+      compression_method: 0,
+      filter_method: 0,
+      interlace_method: 0,
+    };
+    ihdr.validate()?;
+    Ok(ihdr)
+  }
+
+  /// Checks dimensions, the bit depth and color type combination and the
+  /// method fields.
+  pub fn validate(&self) -> Result<()> {
+    if self.width == 0 || self.height == 0 {
+      return Err(invalid("IHDR", format!("image is {}x{}, dimensions must be at least 1", self.width, self.height)));
+    }
+    if self.width > Chunk::MAX_LENGTH || self.height > Chunk::MAX_LENGTH {
+      return Err(invalid("IHDR", format!("image is {}x{}, dimensions must be at most 2^31 - 1", self.width, self.height)));
+    }
+    if !self.color_type.allowed_bit_depths().contains(&self.bit_depth) {
+      return Err(invalid("IHDR", format!("bit depth {} is not allowed for color type {}", self.bit_depth, self.color_type)));
+    }
+    if self.compression_method != 0 {
+      return Err(invalid("IHDR", format!("unknown compression method {}", self.compression_method)));
+    }
+    if self.filter_method != 0 {
+      return Err(invalid("IHDR", format!("unknown filter method {}", self.filter_method)));
+    }
+    if self.interlace_method > 1 {
+      return Err(invalid("IHDR", format!("unknown interlace method {}", self.interlace_method)));
+    }
+    Ok(())
+  }
+
+  /// Returns true for Adam7 interlaced images.
+  pub fn is_interlaced(&self) -> bool {
+    self.interlace_method == 1
+  }
+
+  /// Number of bits per pixel.
+  pub fn bits_per_pixel(&self) -> usize {
+    self.bit_depth as usize * self.color_type.channels() as usize
+  }
+
+  /// Serializes the header into an IHDR chunk.
+  pub fn to_chunk(&self) -> Chunk {
+    let mut data = Vec::with_capacity(Ihdr::LENGTH);
+    data.extend_from_slice(&self.width.to_be_bytes());
+    data.extend_from_slice(&self.height.to_be_bytes());
+    data.extend_from_slice(&[
+      self.bit_depth,
+      self.color_type as u8,
+      self.compression_method,
+      self.filter_method,
+      self.interlace_method,
+    ]);
+    Chunk::new(ChunkType::from_str("IHDR").unwrap(), data)
+  }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    check_type(chunk, "IHDR")?;
+    let data = chunk.data();
+    if data.len() != Ihdr::LENGTH {
+      return Err(invalid("IHDR", format!("length is {}, must be {}", data.len(), Ihdr::LENGTH)));
+    }
+
+    let ihdr = Ihdr {
+      width: u32::from_be_bytes(array4_from_slice(&data[0..4])),
+      height: u32::from_be_bytes(array4_from_slice(&data[4..8])),
+      bit_depth: data[8],
+      color_type: ColorType::try_from(data[9])?,
+      compression_method: data[10],
+      filter_method: data[11],
+      interlace_method: data[12],
+    };
+    ihdr.validate()?;
+    Ok(ihdr)
+  }
+}
+
+impl std::fmt::Display for Ihdr {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "IHDR: {}x{}, {}-bit {}, {}", self.width, self.height, self.bit_depth, self.color_type,
+      if self.is_interlaced() { "adam7 interlaced" } else { "not interlaced" })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::png::Png;
+  use crate::png::tests::PNG_FILE;
+  use crate::PngError;
+
+  #[test]
+  fn test_ihdr_from_chunk() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    let ihdr = Ihdr::try_from(&png.chunks()[0]).unwrap();
+    assert_eq!(ihdr, Ihdr::new(50, 50, 8, ColorType::Rgba).unwrap());
+    assert_eq!(ihdr.bits_per_pixel(), 32);
+    assert!(!ihdr.is_interlaced());
+  }
+
+  #[test]
+  fn test_ihdr_display() {
+    let mut ihdr = Ihdr::new(50, 40, 16, ColorType::GrayscaleAlpha).unwrap();
+    assert_eq!(ihdr.to_string(), "IHDR: 50x40, 16-bit grayscale+alpha, not interlaced");
+    ihdr.interlace_method = 1;
+    assert_eq!(ihdr.to_string(), "IHDR: 50x40, 16-bit grayscale+alpha, adam7 interlaced");
+  }
+
+  #[test]
+  fn test_ihdr_round_trip() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    let chunk = &png.chunks()[0];
+    assert_eq!(Ihdr::try_from(chunk).unwrap().to_chunk().as_bytes(), chunk.as_bytes());
+  }
+
+  #[test]
+  fn test_invalid_ihdr() {
+    assert!(Ihdr::new(0, 10, 8, ColorType::Rgb).is_err());
+    assert!(Ihdr::new(10, 0, 8, ColorType::Rgb).is_err());
+    assert!(Ihdr::new(10, 10, 4, ColorType::Rgb).is_err());
+    assert!(Ihdr::new(10, 10, 16, ColorType::Indexed).is_err());
+    assert!(Ihdr::new(10, 10, 3, ColorType::Grayscale).is_err());
+    assert!(Ihdr::new(10, 10, 1, ColorType::Grayscale).is_ok());
+
+    let mut data = Ihdr::new(10, 10, 8, ColorType::Rgb).unwrap().to_chunk().data().to_vec();
+    data[9] = 5;
+    let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), data);
+    assert!(matches!(Ihdr::try_from(&chunk), Err(PngError::InvalidChunkData { .. })));
+
+    let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 12]);
+    assert!(Ihdr::try_from(&chunk).is_err());
+  }
+}
@@ -0,0 +1,144 @@
+use crate::ihdr::ColorType;
+use crate::{PngError, Result};
+
+/// Decoded pixels. The layout is the same for every color type and bit
+/// depth: rows top to bottom without padding, pixels left to right, the
+/// samples of a pixel in png order (e.g. r, g, b, a). Samples of 8 bits or
+/// less take one byte each and are not scaled, so a 2-bit grayscale sample
+/// is 0..=3. 16-bit samples take two bytes, big endian. Indexed images hold
+/// the palette index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageBuffer {
+  width: u32,
+  height: u32,
+  color_type: ColorType,
+  bit_depth: u8,
+  data: Vec<u8>,
+}
+
+impl ImageBuffer {
+  /// Creates a buffer, checks that the data has exactly the size of the
+  /// layout and that no sample is out of range for the bit depth.
+  pub fn new(width: u32, height: u32, color_type: ColorType, bit_depth: u8, data: Vec<u8>) -> Result<ImageBuffer> {
+    if !color_type.allowed_bit_depths().contains(&bit_depth) {
+      return Err(PngError::InvalidImageData {
+        reason: format!("bit depth {} is not allowed for color type {}", bit_depth, color_type),
+      });
+    }
+    let expected = ImageBuffer::data_len(width, height, color_type, bit_depth)
+      .ok_or_else(|| PngError::InvalidImageData { reason: format!("image of {}x{} is too large", width, height) })?;
+    if data.len() != expected {
+      return Err(PngError::InvalidImageData { reason: format!("expected {} bytes of pixel data, got {}", expected, data.len()) });
+    }
+    if bit_depth < 8 {
+      if let Some(sample) = data.iter().find(|&&s| s >> bit_depth != 0) {
+        return Err(PngError::InvalidImageData { reason: format!("sample {} doesn't fit in {} bits", sample, bit_depth) });
+      }
+    }
+
+    Ok(ImageBuffer {
+      width,
+      height,
+      color_type,
+      bit_depth,
+      data,
+    })
+  }
+
+  /// Returns the width in pixels.
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  /// Returns the height in pixels.
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  /// Returns the color type.
+  pub fn color_type(&self) -> ColorType {
+    self.color_type
+  }
+
+  /// Returns the bit depth of a sample.
+  pub fn bit_depth(&self) -> u8 {
+    self.bit_depth
+  }
+
+  /// Returns all pixel data, see the type docs for the layout.
+  pub fn data(&self) -> &[u8] {
+    &self.data
+  }
+
+  /// Returns the pixel data, consuming the buffer.
+  pub fn into_data(self) -> Vec<u8> {
+    self.data
+  }
+
+  /// Number of bytes per sample, 2 for 16-bit images and 1 otherwise.
+  pub fn bytes_per_sample(&self) -> usize {
+    if self.bit_depth == 16 { 2 } else { 1 }
+  }
+
+  /// Number of bytes per pixel.
+  pub fn bytes_per_pixel(&self) -> usize {
+    self.color_type.channels() as usize * self.bytes_per_sample()
+  }
+
+  /// Returns one row of pixels.
+  pub fn row(&self, y: u32) -> &[u8] {
+    let len = self.width as usize * self.bytes_per_pixel();
+    &self.data[y as usize * len..(y as usize + 1) * len]
+  }
+
+  /// Returns the bytes of one pixel, panics when x or y is out of bounds.
+  pub fn pixel(&self, x: u32, y: u32) -> &[u8] {
+    assert!(x < self.width && y < self.height, "pixel ({}, {}) is outside of the {}x{} image", x, y, self.width, self.height);
+    let bpp = self.bytes_per_pixel();
+    &self.row(y)[x as usize * bpp..(x as usize + 1) * bpp]
+  }
+
+  /// Returns the value of one sample of a pixel, whatever the bit depth.
+  pub fn sample(&self, x: u32, y: u32, channel: usize) -> u16 {
+    let pixel = self.pixel(x, y);
+    if self.bit_depth == 16 {
+      u16::from_be_bytes([pixel[channel * 2], pixel[channel * 2 + 1]])
+    } else {
+      pixel[channel] as u16
+    }
+  }
+
+  /// Size of the pixel data in this layout, `None` if it doesn't fit in memory.
+  fn data_len(width: u32, height: u32, color_type: ColorType, bit_depth: u8) -> Option<usize> {
+    let bytes_per_sample = if bit_depth == 16 { 2 } else { 1 };
+    (width as usize)
+      .checked_mul(height as usize)?
+      .checked_mul(color_type.channels() as usize * bytes_per_sample)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_image_buffer_layout() {
+    let data = vec![
+      0, 1, 0, 2, 0, 3,   255, 255, 0, 0, 0, 0,
+      1, 0, 1, 0, 1, 0,   2, 0, 2, 0, 2, 0,
+    ];
+    let image = ImageBuffer::new(2, 2, ColorType::Rgb, 16, data).unwrap();
+    assert_eq!(image.bytes_per_pixel(), 6);
+    assert_eq!(image.sample(0, 0, 2), 3);
+    assert_eq!(image.sample(1, 0, 0), 65535);
+    assert_eq!(image.pixel(1, 1), &[2, 0, 2, 0, 2, 0]);
+  }
+
+  #[test]
+  fn test_invalid_image_buffer() {
+    assert!(ImageBuffer::new(2, 2, ColorType::Rgb, 8, vec![0; 11]).is_err());
+    assert!(ImageBuffer::new(2, 2, ColorType::Rgb, 4, vec![0; 12]).is_err());
+    assert!(ImageBuffer::new(2, 1, ColorType::Grayscale, 2, vec![3, 4]).is_err());
+    assert!(ImageBuffer::new(2, 1, ColorType::Grayscale, 2, vec![3, 0]).is_ok());
+  }
+}
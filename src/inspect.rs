@@ -0,0 +1,311 @@
+use std::fmt::Write;
+
+use crate::ancillary::Ancillary;
+use crate::chunk::{Chunk, array4_from_slice};
+use crate::chunk_type::ChunkType;
+use crate::ihdr::Ihdr;
+use crate::palette::{Palette, Transparency};
+use crate::png::Png;
+use crate::text::Text;
+use crate::PngError;
+
+/// Characters of text shown for a chunk unless the full data is asked for.
+pub const PREVIEW_CHARS: usize = 64;
+
+/// Bytes of data shown as hex unless the full data is asked for.
+pub const PREVIEW_BYTES: usize = 32;
+
+/// A chunk as it was found in the file, whether it is valid or not.
+#[derive(Debug, Clone)]
+pub struct RawChunk<'a> {
+  pub index: usize,
+  pub offset: usize,
+  /// Not checked, may hold bytes that are not letters.
+  pub chunk_type: ChunkType,
+  pub data: &'a [u8],
+  pub stored_crc: u32,
+  pub computed_crc: u32,
+}
+
+impl RawChunk<'_> {
+  pub fn crc_ok(&self) -> bool {
+    self.stored_crc == self.computed_crc
+  }
+
+  /// The chunk type as text, bytes that are not letters are escaped.
+  pub fn type_name(&self) -> String {
+    self.chunk_type.bytes().iter().flat_map(|&b| std::ascii::escape_default(b)).map(char::from).collect()
+  }
+
+  /// Copies the chunk, `None` if the chunk type is not valid.
+  pub fn to_chunk(&self) -> Option<Chunk> {
+    ChunkType::try_from(self.chunk_type.bytes()).ok()
+      .map(|chunk_type| Chunk::from_checked_parts(chunk_type, self.data.to_vec(), self.stored_crc))
+  }
+}
+
+/// Everything that can be found in a file that is supposed to be a png.
+/// Unlike parsing into a `Png` this keeps going after bad crcs and chunk
+/// types, so all problems can be shown. It stops when a chunk doesn't fit
+/// in the rest of the file.
+#[derive(Debug)]
+pub struct Inspection<'a> {
+  /// The first 8 bytes of the file, or less if it is shorter.
+  pub signature: &'a [u8],
+  pub chunks: Vec<RawChunk<'a>>,
+  /// Why the chunks couldn't be read to the end of the file.
+  pub error: Option<PngError>,
+}
+
+impl<'a> Inspection<'a> {
+  /// Reads all chunks it can find. Never panics, whatever the input.
+  pub fn new(bytes: &'a [u8]) -> Inspection<'a> {
+    let mut inspection = Inspection { signature: &bytes[..bytes.len().min(8)], chunks: Vec::new(), error: None };
+    let mut offset = 8;
+    while offset < bytes.len() {
+      let rest = &bytes[offset..];
+      let index = inspection.chunks.len();
+      if rest.len() < 12 {
+        inspection.error = Some(PngError::TruncatedChunk { index, offset, needed: 12, available: rest.len() });
+        break;
+      }
+      let length = u32::from_be_bytes(array4_from_slice(&rest[..4]));
+      if length > Chunk::MAX_LENGTH {
+        inspection.error = Some(PngError::ChunkTooLong { index, offset, length });
+        break;
+      }
+      let total = length as usize + 12;
+      if rest.len() < total {
+        inspection.error = Some(PngError::TruncatedChunk { index, offset, needed: total, available: rest.len() });
+        break;
+      }
+
+      inspection.chunks.push(RawChunk {
+        index,
+        offset,
+        chunk_type: ChunkType::new(array4_from_slice(&rest[4..8])),
+        data: &rest[8..total - 4],
+        stored_crc: u32::from_be_bytes(array4_from_slice(&rest[total - 4..total])),
+        computed_crc: crc32fast::hash(&rest[4..total - 4]),
+      });
+      offset += total;
+    }
+    inspection
+  }
+
+  pub fn signature_ok(&self) -> bool {
+    self.signature == Png::STANDARD_HEADER
+  }
+
+  /// The first IHDR chunk, if it can be parsed.
+  pub fn header(&self) -> Option<Ihdr> {
+    self.first_valid(b"IHDR").and_then(|c| Ihdr::try_from(&c).ok())
+  }
+
+  /// The first PLTE chunk, if it can be parsed.
+  pub fn palette(&self) -> Option<Palette> {
+    self.first_valid(b"PLTE").and_then(|c| Palette::try_from(&c).ok())
+  }
+
+  fn first_valid(&self, chunk_type: &[u8; 4]) -> Option<Chunk> {
+    self.chunks.iter()
+      .filter(|c| c.chunk_type.bytes() == *chunk_type)
+      .find_map(|c| c.to_chunk())
+  }
+
+  /// Renders a summary: the signature, then every chunk with its position,
+  /// flags, length, crc status and a description or preview of its data.
+  pub fn report(&self, options: &ReportOptions) -> String {
+    let mut out = String::new();
+    if self.signature_ok() {
+      out.push_str("signature: ok\n");
+    } else {
+      let _ = writeln!(out, "signature: invalid ({})", hex(self.signature));
+    }
+
+    let ihdr = self.header();
+    let palette = self.palette();
+
+    for chunk in &self.chunks {
+      let _ = write!(out, "#{} offset {}: {} [{}] length {}, ",
+        chunk.index, chunk.offset, chunk.type_name(), flags(&chunk.chunk_type), chunk.data.len());
+      if chunk.crc_ok() {
+        out.push_str("crc ok\n");
+      } else {
+        let _ = writeln!(out, "crc mismatch (stored {:#010x}, computed {:#010x})", chunk.stored_crc, chunk.computed_crc);
+      }
+
+      let description = chunk.to_chunk().and_then(|c| describe(&c, ihdr.as_ref(), palette.as_ref(), options));
+      match description {
+        Some(description) => {
+          let _ = writeln!(out, "    {}", description);
+          if options.hex {
+            out.push_str(&hex_dump(chunk.data, options));
+          }
+        },
+        None if options.hex => out.push_str(&hex_dump(chunk.data, options)),
+        None => {
+          if let Some(preview) = preview(chunk.data, options) {
+            let _ = writeln!(out, "    {}", preview);
+          }
+        },
+      }
+    }
+
+    if let Some(error) = &self.error {
+      let _ = writeln!(out, "error: {}", error);
+    }
+    out
+  }
+}
+
+/// How much of the chunk data `Inspection::report` shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReportOptions {
+  /// Show all data instead of a preview.
+  pub full: bool,
+  /// Show a hex dump of the data of every chunk.
+  pub hex: bool,
+}
+
+/// Decoded fields of the chunks this crate knows, `None` for other chunks
+/// and for IEND. Invalid known chunks describe the problem.
+pub fn describe(chunk: &Chunk, ihdr: Option<&Ihdr>, palette: Option<&Palette>, options: &ReportOptions) -> Option<String> {
+  let described = match &chunk.chunk_type().bytes() {
+    b"IHDR" => Ihdr::try_from(chunk).map(|ihdr| ihdr.to_string()),
+    b"PLTE" => Palette::try_from(chunk).map(|palette| palette.to_string()),
+    b"IDAT" => Ok(format!("IDAT: {} bytes of compressed image data", chunk.length())),
+    b"IEND" => return None,
+    b"tRNS" => match ihdr {
+      Some(ihdr) => Transparency::from_chunk(chunk, ihdr).map(|t| t.to_string()),
+      None => Ok("tRNS: can't be decoded without a valid IHDR".to_string()),
+    },
+    b"tEXt" | b"zTXt" | b"iTXt" => Text::try_from(chunk).map(|text| {
+      format!("{}: {:?} = {:?}", chunk.chunk_type(), text.keyword(), truncate(text.text(), options))
+    }),
+    _ => match ihdr {
+      Some(ihdr) => Ancillary::from_chunk(chunk, ihdr, palette).map(|a| a.map(|a| a.to_string())).transpose()?,
+      None => return None,
+    },
+  };
+  Some(described.unwrap_or_else(|err| format!("{}: invalid, {}", chunk.chunk_type(), err)))
+}
+
+/// Text for valid utf8, hex otherwise. `None` for empty data.
+pub fn preview(data: &[u8], options: &ReportOptions) -> Option<String> {
+  if data.is_empty() {
+    return None;
+  }
+  match std::str::from_utf8(data) {
+    Ok(text) => Some(format!("{:?}", truncate(text, options))),
+    Err(_) if options.full || data.len() <= PREVIEW_BYTES => Some(hex(data)),
+    Err(_) => Some(format!("{} ... ({} more bytes)", hex(&data[..PREVIEW_BYTES]), data.len() - PREVIEW_BYTES)),
+  }
+}
+
+/// Cuts text after `PREVIEW_CHARS` characters unless the full data is asked for.
+pub fn truncate<'a>(text: &'a str, options: &ReportOptions) -> std::borrow::Cow<'a, str> {
+  match text.char_indices().nth(PREVIEW_CHARS) {
+    Some((end, _)) if !options.full => format!("{}...", &text[..end]).into(),
+    _ => text.into(),
+  }
+}
+
+/// 16 bytes per line, with offsets and printable characters.
+fn hex_dump(data: &[u8], options: &ReportOptions) -> String {
+  let shown = if options.full { data } else { &data[..data.len().min(PREVIEW_BYTES * 2)] };
+  let mut out = String::new();
+  for (line, bytes) in shown.chunks(16).enumerate() {
+    let ascii: String = bytes.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+    let _ = writeln!(out, "    {:04x}  {:<47}  |{}|", line * 16, hex(bytes), ascii);
+  }
+  if shown.len() < data.len() {
+    let _ = writeln!(out, "    ... ({} more bytes)", data.len() - shown.len());
+  }
+  out
+}
+
+/// Bytes as hex, separated by spaces.
+pub fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(" ")
+}
+
+fn flags(chunk_type: &ChunkType) -> String {
+  if !chunk_type.bytes().iter().all(|b| b.is_ascii_alphabetic()) {
+    return "invalid type".to_string();
+  }
+  let mut flags = vec![
+    if chunk_type.is_critical() { "critical" } else { "ancillary" },
+    if chunk_type.is_public() { "public" } else { "private" },
+    if chunk_type.is_safe_to_copy() { "safe to copy" } else { "unsafe to copy" },
+  ];
+  if !chunk_type.is_reserved_bit_valid() {
+    flags.push("reserved bit set");
+  }
+  flags.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::png::tests::PNG_FILE;
+
+  #[test]
+  fn test_report_image_file() {
+    let report = Inspection::new(&PNG_FILE).report(&ReportOptions::default());
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "signature: ok");
+    assert_eq!(lines[1], "#0 offset 8: IHDR [critical, public, unsafe to copy] length 13, crc ok");
+    assert_eq!(lines[2], "    IHDR: 50x50, 8-bit rgba, not interlaced");
+    assert!(report.contains("    pHYs: 3778x3778 px/m (96 DPI)"));
+    assert!(report.contains("RuSt [critical, private, safe to copy]"));
+    assert!(!report.contains("error"));
+  }
+
+  #[test]
+  fn test_report_damaged_file() {
+    let mut bytes = PNG_FILE.to_vec();
+    bytes[0] = 0;
+    // break the crc of IHDR and cut the file in the middle of a chunk
+    bytes[30] ^= 0xff;
+    bytes.truncate(bytes.len() - 6);
+    let inspection = Inspection::new(&bytes);
+    assert!(!inspection.signature_ok());
+    assert!(!inspection.chunks[0].crc_ok());
+    assert!(matches!(inspection.error, Some(PngError::TruncatedChunk { index: 6, .. })));
+
+    let report = inspection.report(&ReportOptions::default());
+    assert!(report.starts_with("signature: invalid (00 50 4e 47 0d 0a 1a 0a)"));
+    assert!(report.contains("crc mismatch (stored"));
+    assert!(report.contains("error: chunk 6"));
+
+    bytes[12] = 0;
+    let inspection = Inspection::new(&bytes);
+    assert_eq!(inspection.chunks[0].type_name(), "\\x00HDR");
+    assert!(inspection.chunks[0].to_chunk().is_none());
+    assert!(inspection.header().is_none());
+  }
+
+  #[test]
+  fn test_previews() {
+    let short = ReportOptions::default();
+    let full = ReportOptions { full: true, hex: false };
+    let long = "x".repeat(100);
+    assert_eq!(preview(long.as_bytes(), &short).unwrap(), format!("\"{}...\"", "x".repeat(PREVIEW_CHARS)));
+    assert_eq!(preview(long.as_bytes(), &full).unwrap(), format!("\"{}\"", long));
+    assert_eq!(preview(&[0xff, 0], &short).unwrap(), "ff 00");
+    assert!(preview(&[0xff; 40], &short).unwrap().ends_with("(8 more bytes)"));
+    assert_eq!(preview(&[], &short), None);
+
+    let dump = hex_dump(b"hello, world!\n and more", &short);
+    assert_eq!(dump.lines().next().unwrap(), "    0000  68 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 0a 20 61  |hello, world!. a|");
+  }
+
+  #[test]
+  fn test_never_panics() {
+    for len in 0..PNG_FILE.len().min(100) {
+      let _ = Inspection::new(&PNG_FILE[..len]).report(&ReportOptions { full: true, hex: true });
+    }
+    let _ = Inspection::new(&[0xff; 20]).report(&ReportOptions::default());
+  }
+}
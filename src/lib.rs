@@ -0,0 +1,28 @@
+pub mod adam7;
+pub mod ancillary;
+pub mod chunk;
+pub mod chunk_ref;
+pub mod chunk_type;
+pub mod decoder;
+pub mod encoder;
+pub mod envelope;
+pub mod error;
+pub mod filter;
+pub mod ihdr;
+pub mod image;
+pub mod inspect;
+pub mod palette;
+pub mod png;
+pub mod png_ref;
+pub mod position;
+pub mod reader;
+pub mod split;
+pub mod text;
+pub mod validate;
+pub mod writer;
+pub mod zlib;
+
+pub use error::PngError;
+
+pub type Error = PngError;
+pub type Result<T> = std::result::Result<T, Error>;
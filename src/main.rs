@@ -1,23 +1,59 @@
 use clap::Parser;
 
 mod args;
-mod chunk;
-mod chunk_type;
 mod commands;
-mod png;
+mod files;
+mod payload;
 
-pub type Error = Box<dyn std::error::Error + Send + Sync>;
-pub type Result<T> = std::result::Result<T, Error>;
+use pngproject::{envelope, PngError, Result};
 
-fn main() -> Result<()> {
-  let args = args::Args::parse();
+fn main() {
+  let args = match args::Args::try_parse() {
+    Ok(args) => args,
+    Err(err) if err.use_stderr() && args::Format::requested() == args::Format::Json => {
+      // only the first line, the rest is the usage text
+      let message = err.to_string();
+      let reason = message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string();
+      commands::report_error(args::Format::Json, &PngError::InvalidInput { reason });
+      std::process::exit(2);
+    },
+    Err(err) => err.exit(),
+  };
 
+  if let Err(err) = run(&args) {
+    commands::report_error(args.format, &err);
+    std::process::exit(1);
+  }
+}
+
+fn run(args: &args::Args) -> Result<()> {
+  let format = args.format;
   if let Some(command) = &args.command {
     match command {
-      args::Cmd::Encode {file, chunk_type, message, output} => commands::encode(file, chunk_type, message, output),
-      args::Cmd::Decode {file, chunk_type} => commands::decode(file, chunk_type),
-      args::Cmd::Remove {file, chunk_type} => commands::remove(file, chunk_type),
-      args::Cmd::Print {file} => commands::print(file),
+      args::Cmd::Encode {file, position, max_chunk_size, input, write, ..} => {
+        let (target, message, output) = command.encode_args().unwrap()?;
+        let destination = write.destination(file, output.as_ref())?;
+        let mut message = payload::read_message(message.as_deref(), input)?;
+        if input.encrypt {
+          message = envelope::seal(&message, payload::password(true)?.as_bytes())?;
+        }
+        commands::encode(file, &target, &message, position, *max_chunk_size, &destination, write, format)?
+      },
+      args::Cmd::Decode {file, output, ..} => commands::decode(file, &command.target().unwrap(), output, format)?,
+      args::Cmd::Remove {file, output, force, write, ..} => {
+        let destination = write.destination(file, output.as_ref())?;
+        commands::remove(file, &command.target().unwrap(), *force, &destination, write, format)?
+      },
+      args::Cmd::Update {file, chunk_type, message, index, output, write} => {
+        let destination = write.destination(file, output.as_ref())?;
+        commands::update(file, chunk_type, message, *index, &destination, write, format)?
+      },
+      args::Cmd::Print {file, full, hex} => commands::print(file, *full, *hex, format)?,
+      args::Cmd::Validate {files} => {
+        if !commands::validate(files, format)? {
+          std::process::exit(1);
+        }
+      },
     }
   }
   Ok(())
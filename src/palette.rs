@@ -0,0 +1,184 @@
+use std::str::FromStr;
+
+use crate::chunk::{Chunk, check_type, invalid};
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::{Error, Result};
+
+/// The PLTE chunk, a list of rgb entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+  pub entries: Vec<[u8; 3]>,
+}
+
+impl Palette {
+  /// Checks the palette against the header: not allowed for grayscale
+  /// images, between 1 and 256 entries and for indexed images no more
+  /// entries than the bit depth can address.
+  pub fn validate(&self, ihdr: &Ihdr) -> Result<()> {
+    if matches!(ihdr.color_type, ColorType::Grayscale | ColorType::GrayscaleAlpha) {
+      return Err(invalid("PLTE", format!("not allowed for color type {}", ihdr.color_type)));
+    }
+    if self.entries.is_empty() || self.entries.len() > 256 {
+      return Err(invalid("PLTE", format!("has {} entries, must be 1 to 256", self.entries.len())));
+    }
+    if ihdr.color_type == ColorType::Indexed && self.entries.len() > 1 << ihdr.bit_depth {
+      return Err(invalid("PLTE", format!("has {} entries, at most {} allowed at bit depth {}",
+        self.entries.len(), 1 << ihdr.bit_depth, ihdr.bit_depth)));
+    }
+    Ok(())
+  }
+
+  /// Serializes the palette into a PLTE chunk.
+  pub fn to_chunk(&self) -> Chunk {
+    Chunk::new(ChunkType::from_str("PLTE").unwrap(), self.entries.concat())
+  }
+}
+
+impl TryFrom<&Chunk> for Palette {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    check_type(chunk, "PLTE")?;
+    let data = chunk.data();
+    if !data.len().is_multiple_of(3) {
+      return Err(invalid("PLTE", format!("length {} is not a multiple of 3", data.len())));
+    }
+    Ok(Palette {
+      entries: data.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect(),
+    })
+  }
+}
+
+impl std::fmt::Display for Palette {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "PLTE: {} entries", self.entries.len())
+  }
+}
+
+/// The tRNS chunk. Its layout depends on the color type, so it can only
+/// be parsed together with the header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+  /// Alpha for the first palette entries, the rest are opaque.
+  Indexed(Vec<u8>),
+  /// The gray sample value that is fully transparent.
+  Gray(u16),
+  /// The rgb sample values that are fully transparent.
+  Rgb(u16, u16, u16),
+}
+
+impl Transparency {
+  /// Parses a tRNS chunk for an image with the given header.
+  pub fn from_chunk(chunk: &Chunk, ihdr: &Ihdr) -> Result<Transparency> {
+    check_type(chunk, "tRNS")?;
+    let data = chunk.data();
+    let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+    let transparency = match ihdr.color_type {
+      ColorType::Indexed => Transparency::Indexed(data.to_vec()),
+      ColorType::Grayscale if data.len() == 2 => Transparency::Gray(sample(0)),
+      ColorType::Rgb if data.len() == 6 => Transparency::Rgb(sample(0), sample(2), sample(4)),
+      ColorType::Grayscale | ColorType::Rgb =>
+        return Err(invalid("tRNS", format!("length {} is wrong for color type {}", data.len(), ihdr.color_type))),
+      ColorType::GrayscaleAlpha | ColorType::Rgba =>
+        return Err(invalid("tRNS", format!("not allowed for color type {}", ihdr.color_type))),
+    };
+    transparency.validate(ihdr, None)?;
+    Ok(transparency)
+  }
+
+  /// Checks the transparency against the header and, for indexed images,
+  /// that it has no more entries than the palette.
+  pub fn validate(&self, ihdr: &Ihdr, palette: Option<&Palette>) -> Result<()> {
+    let max = if ihdr.bit_depth == 16 { u16::MAX } else { (1u16 << ihdr.bit_depth) - 1 };
+    match (self, ihdr.color_type) {
+      (Transparency::Indexed(alphas), ColorType::Indexed) => {
+        if alphas.len() > 1 << ihdr.bit_depth {
+          return Err(invalid("tRNS", format!("has {} entries, at most {} allowed at bit depth {}",
+            alphas.len(), 1 << ihdr.bit_depth, ihdr.bit_depth)));
+        }
+        if let Some(palette) = palette {
+          if alphas.len() > palette.entries.len() {
+            return Err(invalid("tRNS", format!("has {} entries, the palette only {}", alphas.len(), palette.entries.len())));
+          }
+        }
+      },
+      (Transparency::Gray(gray), ColorType::Grayscale) if *gray > max =>
+        return Err(invalid("tRNS", format!("gray value {} doesn't fit in {} bits", gray, ihdr.bit_depth))),
+      (Transparency::Rgb(r, g, b), ColorType::Rgb) if *r.max(g).max(b) > max =>
+        return Err(invalid("tRNS", format!("rgb value ({}, {}, {}) doesn't fit in {} bits", r, g, b, ihdr.bit_depth))),
+      (Transparency::Gray(_), ColorType::Grayscale) | (Transparency::Rgb(..), ColorType::Rgb) => {},
+      _ => return Err(invalid("tRNS", format!("doesn't match color type {}", ihdr.color_type))),
+    }
+    Ok(())
+  }
+
+  /// Serializes the transparency into a tRNS chunk.
+  pub fn to_chunk(&self) -> Chunk {
+    let data = match self {
+      Transparency::Indexed(alphas) => alphas.clone(),
+      Transparency::Gray(gray) => gray.to_be_bytes().to_vec(),
+      Transparency::Rgb(r, g, b) => [r.to_be_bytes(), g.to_be_bytes(), b.to_be_bytes()].concat(),
+    };
+    Chunk::new(ChunkType::from_str("tRNS").unwrap(), data)
+  }
+}
+
+impl std::fmt::Display for Transparency {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Transparency::Indexed(alphas) => write!(f, "tRNS: alpha for {} palette entries", alphas.len()),
+      Transparency::Gray(gray) => write!(f, "tRNS: gray {}", gray),
+      Transparency::Rgb(r, g, b) => write!(f, "tRNS: rgb ({}, {}, {})", r, g, b),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+  }
+
+  #[test]
+  fn test_palette_round_trip() {
+    let palette = Palette::try_from(&chunk("PLTE", vec![1, 2, 3, 4, 5, 6])).unwrap();
+    assert_eq!(palette.entries, vec![[1, 2, 3], [4, 5, 6]]);
+    assert_eq!(palette.to_chunk().data(), &[1, 2, 3, 4, 5, 6]);
+    assert!(Palette::try_from(&chunk("PLTE", vec![1, 2, 3, 4])).is_err());
+    assert!(Palette::try_from(&chunk("tRNS", vec![1, 2, 3])).is_err());
+  }
+
+  #[test]
+  fn test_palette_validate() {
+    let palette = Palette { entries: vec![[0; 3]; 5] };
+    assert!(palette.validate(&Ihdr::new(1, 1, 4, ColorType::Indexed).unwrap()).is_ok());
+    assert!(palette.validate(&Ihdr::new(1, 1, 2, ColorType::Indexed).unwrap()).is_err());
+    assert!(palette.validate(&Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap()).is_err());
+    assert!(palette.validate(&Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap()).is_ok());
+    assert!(Palette { entries: vec![] }.validate(&Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap()).is_err());
+  }
+
+  #[test]
+  fn test_transparency_from_chunk() {
+    let indexed = Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap();
+    let trns = Transparency::from_chunk(&chunk("tRNS", vec![0, 128]), &indexed).unwrap();
+    assert_eq!(trns, Transparency::Indexed(vec![0, 128]));
+    assert!(trns.validate(&indexed, Some(&Palette { entries: vec![[0; 3]] })).is_err());
+
+    let gray = Ihdr::new(1, 1, 4, ColorType::Grayscale).unwrap();
+    assert_eq!(Transparency::from_chunk(&chunk("tRNS", vec![0, 15]), &gray).unwrap(), Transparency::Gray(15));
+    assert!(Transparency::from_chunk(&chunk("tRNS", vec![0, 16]), &gray).is_err());
+    assert!(Transparency::from_chunk(&chunk("tRNS", vec![0]), &gray).is_err());
+
+    let rgb = Ihdr::new(1, 1, 16, ColorType::Rgb).unwrap();
+    let trns = Transparency::from_chunk(&chunk("tRNS", vec![0, 1, 0, 2, 0, 3]), &rgb).unwrap();
+    assert_eq!(trns, Transparency::Rgb(1, 2, 3));
+    assert_eq!(trns.to_chunk().data(), &[0, 1, 0, 2, 0, 3]);
+
+    let rgba = Ihdr::new(1, 1, 8, ColorType::Rgba).unwrap();
+    assert!(Transparency::from_chunk(&chunk("tRNS", vec![0, 1]), &rgba).is_err());
+  }
+}
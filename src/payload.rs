@@ -0,0 +1,97 @@
+use std::io::Read;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use pngproject::{PngError, Result};
+
+use crate::args::{Encoding, InputArgs};
+
+/// The message for encode as bytes: MESSAGE, the input file or stdin,
+/// decoded from hex or base64 if asked for.
+pub fn read_message(message: Option<&str>, input: &InputArgs) -> Result<Vec<u8>> {
+  let data = match (&input.input_file, input.stdin) {
+    (Some(path), _) => std::fs::read(path)?,
+    (None, true) => {
+      let mut data = Vec::new();
+      std::io::stdin().read_to_end(&mut data)?;
+      data
+    },
+    (None, false) => message
+      .ok_or_else(|| PngError::InvalidInput { reason: "no MESSAGE given".to_string() })?
+      .as_bytes().to_vec(),
+  };
+  from_text(&data, input.encoding())
+}
+
+/// Environment variable the password for --encrypt and --decrypt is taken
+/// from, so scripts don't have to answer a prompt.
+pub const PASSWORD_VAR: &str = "PNGPROJECT_PASSWORD";
+
+/// The password from the environment, or asked for on the terminal. A new
+/// password is asked for twice.
+pub fn password(new: bool) -> Result<String> {
+  if let Ok(password) = std::env::var(PASSWORD_VAR) {
+    return non_empty(password);
+  }
+  let password = rpassword::prompt_password("Password: ")?;
+  if new && rpassword::prompt_password("Repeat password: ")? != password {
+    return Err(PngError::InvalidInput { reason: "the passwords don't match".to_string() });
+  }
+  non_empty(password)
+}
+
+fn non_empty(password: String) -> Result<String> {
+  match password.is_empty() {
+    true => Err(PngError::InvalidInput { reason: "the password is empty".to_string() }),
+    false => Ok(password),
+  }
+}
+
+/// Decodes hex or base64, whitespace in between is ignored so wrapped
+/// lines work. Other encodings are returned as they are.
+pub fn from_text(data: &[u8], encoding: Encoding) -> Result<Vec<u8>> {
+  let text: Vec<u8> = data.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+  let invalid = |name: &str, err: &dyn std::fmt::Display| PngError::InvalidInput { reason: format!("not valid {}: {}", name, err) };
+  match encoding {
+    Encoding::Hex => hex::decode(text).map_err(|err| invalid("hex", &err)),
+    Encoding::Base64 => STANDARD.decode(text).map_err(|err| invalid("base64", &err)),
+    Encoding::Text | Encoding::Raw => Ok(data.to_vec()),
+  }
+}
+
+/// Chunk data as text in the encoding. Text must be utf8, raw data can't
+/// be shown as text at all.
+pub fn to_text(data: &[u8], encoding: Encoding, chunk_type: &str) -> Result<String> {
+  match encoding {
+    Encoding::Text => std::str::from_utf8(data).map(str::to_string).map_err(|err| PngError::InvalidUtf8 {
+      chunk_type: chunk_type.to_string(),
+      valid_up_to: err.valid_up_to(),
+    }),
+    Encoding::Hex => Ok(hex::encode(data)),
+    Encoding::Base64 => Ok(STANDARD.encode(data)),
+    Encoding::Raw => Err(PngError::InvalidInput { reason: "raw data can't be shown as text".to_string() }),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_round_trip() {
+    let data: Vec<u8> = (0..=255).collect();
+    for encoding in [Encoding::Hex, Encoding::Base64] {
+      let text = to_text(&data, encoding, "ruSt").unwrap();
+      assert_eq!(from_text(text.as_bytes(), encoding).unwrap(), data);
+    }
+    assert_eq!(from_text(b"89 50\n4e 47\n", Encoding::Hex).unwrap(), b"\x89PNG");
+    assert_eq!(from_text(b"iVBO\nRw==", Encoding::Base64).unwrap(), b"\x89PNG");
+  }
+
+  #[test]
+  fn test_invalid() {
+    assert!(matches!(from_text(b"8g", Encoding::Hex), Err(PngError::InvalidInput { .. })));
+    assert!(matches!(from_text(b"a", Encoding::Base64), Err(PngError::InvalidInput { .. })));
+    assert!(matches!(to_text(&[0x89, 0x50], Encoding::Text, "ruSt"), Err(PngError::InvalidUtf8 { valid_up_to: 0, .. })));
+  }
+}
@@ -1,8 +1,7 @@
-use std::str::FromStr;
+use std::io::{Read, Write};
 
-use crate::{chunk::{Chunk, array4_from_slice}, chunk_type::{ChunkType, self}};
-// use std::fmt::Error;
-use crate::{Error, Result};
+use crate::{chunk::Chunk, decoder, encoder::Encoder, ihdr::Ihdr, image::ImageBuffer, inspect::{Inspection, ReportOptions}, palette::{Palette, Transparency}, png_ref::PngRef, position::{self, Position}, reader::PngReader, text::{self, Text}, validate, writer::PngWriter, zlib};
+use crate::{Error, PngError, Result};
 
 pub struct Png {
   header: [u8; 8],
@@ -29,28 +28,71 @@ impl Png {
     self.chunks.push(chunk);
   }
 
-  /// Insert chunk at second to last position. This way the 'IEND' chunk is last
-  /// and file stays valid.
-  pub fn insert_chunk(&mut self, chunk: Chunk) {
-    // appends chunk at second last position
-    self.chunks.insert(self.chunks.len() - 2, chunk);
+  /// Inserts chunk right before IEND, or at the end if there is none, and
+  /// returns its index. See `insert_chunk_at`.
+  pub fn insert_chunk(&mut self, chunk: Chunk) -> Result<usize> {
+    self.insert_chunk_at(chunk, Position::BeforeIend)
   }
 
-  /// Remove chunk with type chunk_type from vector of chunks.
-  /// FIXME remove all chunks with given type, error handling..
+  /// Inserts chunk at the position and returns its index. Placements the
+  /// spec forbids, like anything after IEND or an ancillary chunk between
+  /// two IDATs, are refused.
+  pub fn insert_chunk_at(&mut self, chunk: Chunk, position: Position) -> Result<usize> {
+    let index = position.index_in(&self.chunks)?;
+    position::check_placement(chunk.chunk_type(), &self.chunks, index)?;
+    self.chunks.insert(index, chunk);
+    Ok(index)
+  }
+
+  /// Remove the first chunk with type chunk_type from vector of chunks. See
+  /// `remove_chunks_where` to remove all of them.
   pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
-    let mut chunks_copy = self.chunks.clone();
-    let pos = self.chunks.iter().position(|c| c.chunk_type() == &ChunkType::from_str(chunk_type).unwrap());
+    let pos = self.chunks.iter().position(|c| c.chunk_type().bytes() == chunk_type.as_bytes());
     match pos {
-      Some(pos) => {
-        let found_chunk = Some(self.chunks[pos].clone());
-        chunks_copy.remove(pos);
-        self.chunks = chunks_copy;
-        return Ok(found_chunk.unwrap().clone());
-      },
-      None => return Err(Box::new(std::fmt::Error))
+      Some(pos) => Ok(self.chunks.remove(pos)),
+      None => Err(PngError::ChunkNotFound { chunk_type: chunk_type.to_string() })
     }
-	}
+  }
+
+  /// Replaces the first chunk with type chunk_type, keeping its position,
+  /// and returns the old chunk.
+  pub fn replace_chunk(&mut self, chunk_type: &str, new_chunk: Chunk) -> Result<Chunk> {
+    self.replace_nth_chunk(chunk_type, 0, new_chunk)
+  }
+
+  /// Replaces the chunk with type chunk_type that comes `n`th in file
+  /// order, counting from 0, and returns the old chunk.
+  pub fn replace_nth_chunk(&mut self, chunk_type: &str, n: usize, new_chunk: Chunk) -> Result<Chunk> {
+    let chunk = self.nth_chunk_mut(chunk_type, n)?;
+    Ok(std::mem::replace(chunk, new_chunk))
+  }
+
+  /// Returns the chunk with type chunk_type that comes `n`th in file order,
+  /// counting from 0, for editing it in place.
+  pub fn nth_chunk_mut(&mut self, chunk_type: &str, n: usize) -> Result<&mut Chunk> {
+    let count = self.chunks_by_type(chunk_type).len();
+    self.chunks.iter_mut()
+      .filter(|c| c.chunk_type().bytes() == chunk_type.as_bytes())
+      .nth(n)
+      .ok_or_else(|| match count {
+        0 => PngError::ChunkNotFound { chunk_type: chunk_type.to_string() },
+        _ => PngError::ChunkIndexOutOfRange { chunk_type: chunk_type.to_string(), index: n, count },
+      })
+  }
+
+  /// Removes every chunk the predicate is true for and returns them in
+  /// file order.
+  pub fn remove_chunks_where<F: FnMut(&Chunk) -> bool>(&mut self, mut predicate: F) -> Vec<Chunk> {
+    let (removed, kept) = std::mem::take(&mut self.chunks).into_iter().partition(|c| predicate(c));
+    self.chunks = kept;
+    removed
+  }
+
+  /// Checks the chunk order rules of the spec and returns every violation,
+  /// see `validate::validate`.
+  pub fn validate(&self) -> Vec<PngError> {
+    validate::validate(&self.chunks)
+  }
 
   /// Returns the header of the png.
   pub fn header(&self) -> &[u8; 8] {
@@ -62,10 +104,156 @@ impl Png {
     &self.chunks
   }
 
-  /// Returns the chunk with the given chunk type.
-  /// FIXME: multiple matching chunks are not handled.
+  /// Returns the first chunk with the given chunk type, see
+  /// `chunks_by_type` for all of them.
   pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-    self.chunks.iter().find(|&c| c.chunk_type() == &ChunkType::from_str(chunk_type).unwrap())
+    self.chunks.iter().find(|c| c.chunk_type().bytes() == chunk_type.as_bytes())
+  }
+
+  /// Returns every chunk with the given chunk type in file order.
+  pub fn chunks_by_type(&self, chunk_type: &str) -> Vec<&Chunk> {
+    self.chunks.iter().filter(|c| c.chunk_type().bytes() == chunk_type.as_bytes()).collect()
+  }
+
+  /// Returns the parsed and validated IHDR chunk.
+  pub fn header_info(&self) -> Result<Ihdr> {
+    let chunk = self.chunk_by_type("IHDR")
+      .ok_or_else(|| PngError::ChunkNotFound { chunk_type: "IHDR".to_string() })?;
+    Ihdr::try_from(chunk)
+  }
+
+  /// Returns the parsed PLTE chunk, validated against the header. `None`
+  /// if there is none.
+  pub fn palette(&self) -> Result<Option<Palette>> {
+    let Some(chunk) = self.chunk_by_type("PLTE") else { return Ok(None) };
+    let palette = Palette::try_from(chunk)?;
+    palette.validate(&self.header_info()?)?;
+    Ok(Some(palette))
+  }
+
+  /// Returns the parsed tRNS chunk, validated against the header and the
+  /// palette. `None` if there is none.
+  pub fn transparency(&self) -> Result<Option<Transparency>> {
+    let Some(chunk) = self.chunk_by_type("tRNS") else { return Ok(None) };
+    let ihdr = self.header_info()?;
+    let transparency = Transparency::from_chunk(chunk, &ihdr)?;
+    transparency.validate(&ihdr, self.palette()?.as_ref())?;
+    Ok(Some(transparency))
+  }
+
+  /// Returns all tEXt, zTXt and iTXt chunks in file order, decompressed.
+  pub fn texts(&self) -> Result<Vec<Text>> {
+    self.chunks.iter()
+      .filter(|c| Text::CHUNK_TYPES.contains(&c.chunk_type().to_string().as_str()))
+      .map(Text::try_from)
+      .collect()
+  }
+
+  /// Returns the first text with the keyword, decompressed. Other texts are
+  /// not decompressed.
+  pub fn text(&self, keyword: &str) -> Result<Text> {
+    let chunk = self.chunks.iter()
+      .find(|c| text::keyword(c).is_ok_and(|k| k == keyword))
+      .ok_or_else(|| PngError::KeywordNotFound { keyword: keyword.to_string() })?;
+    Text::try_from(chunk)
+  }
+
+  /// Stores a text under the keyword, see `Text::new` for the chunk type
+  /// that is used and `insert_text` for where it goes.
+  pub fn set_text(&mut self, keyword: &str, value: &str) -> Result<()> {
+    self.insert_text(Text::new(keyword, value)?);
+    Ok(())
+  }
+
+  /// Adds a text chunk. Existing texts with the same keyword are replaced,
+  /// a new one goes right before IEND.
+  pub fn insert_text(&mut self, text: Text) {
+    let keyword = text.keyword().to_string();
+    let same_keyword = |c: &Chunk| text::keyword(c).is_ok_and(|k| k == keyword);
+    let position = self.chunks.iter().position(same_keyword)
+      .or_else(|| self.chunks.iter().position(|c| c.chunk_type().bytes() == *b"IEND"))
+      .unwrap_or(self.chunks.len());
+    self.chunks.insert(position, text.to_chunk());
+    let mut index = 0;
+    self.chunks.retain(|c| {
+      index += 1;
+      index - 1 == position || !same_keyword(c)
+    });
+  }
+
+  /// Removes all texts with the keyword and returns them. If one of them
+  /// can't be read none is removed.
+  pub fn remove_text(&mut self, keyword: &str) -> Result<Vec<Text>> {
+    let same_keyword = |c: &Chunk| text::keyword(c).is_ok_and(|k| k == keyword);
+    let removed = self.chunks.iter()
+      .filter(|c| same_keyword(c))
+      .map(Text::try_from)
+      .collect::<Result<Vec<Text>>>()?;
+    if removed.is_empty() {
+      return Err(PngError::KeywordNotFound { keyword: keyword.to_string() });
+    }
+    self.chunks.retain(|c| !same_keyword(c));
+    Ok(removed)
+  }
+
+  /// Joins the IDAT chunks and inflates them, the result is the filtered
+  /// scanlines as stored in the file. Inflating stops with an error as soon
+  /// as there is more data than the header asks for.
+  pub fn image_data(&self) -> Result<Vec<u8>> {
+    let ihdr = self.header_info()?;
+    let expected = decoder::expected_data_len(&ihdr).ok_or_else(|| PngError::InvalidImageData {
+      reason: format!("image of {}x{} is too large", ihdr.width, ihdr.height),
+    })?;
+    let idats = self.chunks.iter()
+      .enumerate()
+      .filter(|(_, c)| c.chunk_type().bytes() == *b"IDAT")
+      .map(|(index, c)| (index, c.data()));
+    zlib::inflate_chunks_limited(idats, expected).map_err(|err| match err {
+      PngError::InflateLimit { .. } => PngError::InvalidImageData {
+        reason: format!("image data inflates to more than the {} bytes the header asks for", expected),
+      },
+      err => err,
+    })
+  }
+
+  /// Decodes the pixels, see `decoder::decode`.
+  pub fn decode(&self) -> Result<ImageBuffer> {
+    decoder::decode(self)
+  }
+
+  /// Decodes the pixels and looks up the palette, see `decoder::decode_rgba`.
+  pub fn decode_rgba(&self) -> Result<ImageBuffer> {
+    decoder::decode_rgba(self)
+  }
+
+  /// Decodes the seven reduced images of an interlaced png, see
+  /// `decoder::decode_passes`.
+  pub fn decode_passes(&self) -> Result<Vec<ImageBuffer>> {
+    decoder::decode_passes(self)
+  }
+
+  /// Encodes pixels into a png with the default encoder settings, see
+  /// `encoder::Encoder` for more control and for the palette indexed
+  /// images need.
+  pub fn from_image(image: &ImageBuffer) -> Result<Png> {
+    Encoder::default().encode(image)
+  }
+
+  /// Reads a png from any reader, chunk by chunk. Prefer this over reading
+  /// everything into memory first and using `try_from`.
+  pub fn from_reader<R: Read>(reader: R) -> Result<Png> {
+    let chunks = PngReader::new(reader)?.collect::<Result<Vec<Chunk>>>()?;
+    Ok(Png::from_chunks(chunks))
+  }
+
+  /// Writes the png chunk by chunk to the writer, see `PngWriter` for the
+  /// ordering rules that are enforced.
+  pub fn write_to<W: Write>(&self, writer: W) -> Result<W> {
+    let mut writer = PngWriter::new(writer)?;
+    for chunk in &self.chunks {
+      writer.write_chunk(chunk)?;
+    }
+    writer.finish()
   }
 
   /// Returns the png as bytes.
@@ -83,36 +271,15 @@ impl TryFrom<&[u8]> for Png {
   type Error = Error;
 
   fn try_from(bytes: &[u8]) -> Result<Self> {
-    // too small
-    if bytes.len() < 8 {
-        return Err(Box::new(std::fmt::Error));
-    }
-    // header identifying png file
-    if bytes[..8] != Png::STANDARD_HEADER {
-      return Err(Box::new(std::fmt::Error));
-    }
-
-    let mut chunks = Vec::<Chunk>::new();
-    // iterate over bytes and read one chunk at a time
-    let mut cursor = 8; // loc of first byte to read
-    while cursor < bytes.len() {
-      // first four bytes is length of chunk data
-      // so total chunk is length (4) + type (4) + data + crc (4) = 12 + length
-      let length = u32::from_be_bytes(array4_from_slice(&bytes[cursor..cursor + 4])) as usize;
-      chunks.push(Chunk::try_from(&bytes[cursor..cursor + 4 + length + 4 + 4]).unwrap());
-      cursor += 12 + length
-    }
-
-    Ok(Png{
-      header: array8_from_slice(&bytes[..8]),
-      chunks
-    })
-
-}}
+    let png = PngRef::try_from(bytes)?;
+    Ok(Png::new(Png::STANDARD_HEADER, png.chunks().iter().map(|c| c.to_chunk()).collect()))
+  }
+}
 
+/// A summary of the chunks, see `Inspection::report`.
 impl std::fmt::Display for Png {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "{:?}", self.as_bytes())
+    write!(f, "{}", Inspection::new(&self.as_bytes()).report(&ReportOptions::default()))
   }
 }
 
@@ -127,21 +294,18 @@ pub fn array8_from_slice(slice: &[u8]) -> [u8; 8] {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
   use super::*;
   use crate::chunk_type::ChunkType;
   use crate::chunk::Chunk;
-  use std::str::FromStr;
   use std::convert::TryFrom;
 
   fn testing_chunks() -> Vec<Chunk> {
-    let mut chunks = Vec::new();
-
-    chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-    chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-    chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-    chunks
+    vec![
+      chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+      chunk_from_strings("miDl", "I am another chunk").unwrap(),
+      chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+    ]
   }
 
   fn testing_png() -> Png {
@@ -225,6 +389,114 @@ mod tests {
   }
 
 
+  #[test]
+  fn test_errors_carry_position() {
+    let mut bytes = Png::STANDARD_HEADER.to_vec();
+    let chunks = testing_chunks();
+    bytes.extend(chunks[0].as_bytes());
+    let mut bad = chunks[1].as_bytes();
+    bad[4] = b'1';
+    bytes.extend(bad);
+
+    match Png::try_from(bytes.as_ref()) {
+      Err(PngError::CrcMismatch { index, offset, .. }) => {
+        assert_eq!(index, 1);
+        assert_eq!(offset, 8 + chunks[0].as_bytes().len());
+      },
+      other => panic!("expected crc mismatch, got {:?}", other.err()),
+    }
+
+    let png = Png::try_from(&[13, 80, 78][..]);
+    assert!(matches!(png, Err(PngError::InvalidSignature { .. })));
+  }
+
+  #[test]
+  fn test_insert_chunk() {
+    let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+    let chunk = || chunk_from_strings("ruSt", "hidden").unwrap();
+    assert_eq!(png.insert_chunk(chunk()).unwrap(), 6);
+    assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+    assert_eq!(png.insert_chunk_at(chunk(), Position::AfterIhdr).unwrap(), 1);
+    assert_eq!(png.insert_chunk_at(chunk(), Position::BeforeFirstIdat).unwrap(), 5);
+    assert!(png.insert_chunk_at(chunk(), Position::Index(0)).is_err());
+    assert!(png.insert_chunk_at(chunk(), Position::Index(png.chunks().len())).is_err());
+    assert_eq!(png.chunks().len(), 10);
+
+    // a png without IEND gets the chunk at the end
+    let mut png = testing_png();
+    assert_eq!(png.insert_chunk(chunk()).unwrap(), 3);
+  }
+
+  #[test]
+  fn test_remove_chunks_where() {
+    let mut png = testing_png();
+    png.append_chunk(chunk_from_strings("miDl", "I am the second middle chunk").unwrap());
+    assert_eq!(png.chunks_by_type("miDl").len(), 2);
+    assert!(png.chunks_by_type("nOne").is_empty());
+
+    let removed = png.remove_chunks_where(|c| c.chunk_type().to_string() == "miDl");
+    assert_eq!(removed.len(), 2);
+    assert_eq!(removed[1].data_as_string().unwrap(), "I am the second middle chunk");
+    let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+    assert_eq!(types, vec!["FrSt", "LASt"]);
+    assert!(png.remove_chunks_where(|_| false).is_empty());
+  }
+
+  #[test]
+  fn test_replace_chunk() {
+    let mut png = testing_png();
+    png.append_chunk(chunk_from_strings("miDl", "I am the second middle chunk").unwrap());
+    let old = png.replace_nth_chunk("miDl", 1, chunk_from_strings("miDl", "replaced").unwrap()).unwrap();
+    assert_eq!(old.data_as_string().unwrap(), "I am the second middle chunk");
+    assert_eq!(png.chunks()[3].data_as_string().unwrap(), "replaced");
+
+    png.replace_chunk("FrSt", chunk_from_strings("NeWf", "new first").unwrap()).unwrap();
+    assert_eq!(png.chunks()[0].chunk_type().to_string(), "NeWf");
+
+    png.nth_chunk_mut("miDl", 0).unwrap().set_data(b"edited".to_vec()).unwrap();
+    assert_eq!(png.chunks()[1].data_as_string().unwrap(), "edited");
+    assert!(matches!(png.replace_nth_chunk("miDl", 2, chunk_from_strings("miDl", "x").unwrap()),
+      Err(PngError::ChunkIndexOutOfRange { index: 2, count: 2, .. })));
+    assert!(matches!(png.nth_chunk_mut("noNe", 0), Err(PngError::ChunkNotFound { .. })));
+    assert!(png.replace_chunk("nOne", chunk_from_strings("nOne", "x").unwrap()).is_err());
+  }
+
+  #[test]
+  fn test_remove_missing_chunk() {
+    let mut png = testing_png();
+    assert!(matches!(png.remove_chunk("TeSt"), Err(PngError::ChunkNotFound { .. })));
+    // not a chunk type at all, nothing can match
+    assert!(matches!(png.remove_chunk("T3st"), Err(PngError::ChunkNotFound { .. })));
+    assert!(png.chunk_by_type("toolong").is_none());
+  }
+
+  #[test]
+  fn test_truncated_file_does_not_panic() {
+    // cutting the file at a chunk boundary still gives a parsable file
+    let mut boundaries = vec![8];
+    for chunk in Png::try_from(&PNG_FILE[..]).unwrap().chunks() {
+      boundaries.push(boundaries.last().unwrap() + 12 + chunk.length() as usize);
+    }
+    for len in 0..PNG_FILE.len() {
+      let png = Png::try_from(&PNG_FILE[..len]);
+      assert_eq!(png.is_ok(), boundaries.contains(&len));
+    }
+  }
+
+  #[test]
+  fn test_huge_length_field() {
+    let mut bytes = PNG_FILE.to_vec();
+    // length field of the first chunk (IHDR)
+    bytes[8..12].copy_from_slice(&[0xff, 0xff, 0xff, 0xf0]);
+    assert!(matches!(Png::try_from(bytes.as_ref()), Err(PngError::ChunkTooLong { index: 0, offset: 8, .. })));
+
+    bytes[8..12].copy_from_slice(&[0x7f, 0xff, 0xff, 0xff]);
+    match Png::try_from(bytes.as_ref()) {
+      Err(PngError::TruncatedChunk { index: 0, offset: 8, available, .. }) => assert_eq!(available, PNG_FILE.len() - 8),
+      other => panic!("expected truncated chunk, got {:?}", other.err()),
+    }
+  }
+
   #[test]
   fn test_list_chunks() {
     let png = testing_png();
@@ -232,6 +504,61 @@ mod tests {
     assert_eq!(chunks.len(), 3);
   }
 
+  #[test]
+  fn test_set_text() {
+    let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+    png.set_text("Title", "first").unwrap();
+    png.set_text("Author", "someone").unwrap();
+    png.set_text("Title", "second ✓").unwrap();
+    let texts = png.texts().unwrap();
+    let pairs: Vec<(&str, &str)> = texts.iter().map(|t| (t.keyword(), t.text())).collect();
+    assert_eq!(pairs, vec![("Title", "second ✓"), ("Author", "someone")]);
+    assert_eq!(png.text("Author").unwrap().text(), "someone");
+    assert!(matches!(png.text("Nobody"), Err(PngError::KeywordNotFound { .. })));
+    assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+    assert!(png.set_text(" bad", "x").is_err());
+
+    assert_eq!(png.remove_text("Title").unwrap()[0].text(), "second ✓");
+    assert_eq!(png.texts().unwrap().len(), 1);
+    assert!(matches!(png.remove_text("Title"), Err(PngError::KeywordNotFound { .. })));
+  }
+
+  #[test]
+  fn test_text_keyword_round_trip() {
+    use crate::text::{CompressedTextChunk, InternationalTextChunk, TextChunk};
+
+    let texts = [
+      Text::Plain(TextChunk::new("Comment", "plain").unwrap()),
+      Text::Compressed(CompressedTextChunk::new("Comment", "compressed").unwrap()),
+      Text::International(InternationalTextChunk::new("Title", "Grüße").unwrap()
+        .with_compression(true).unwrap()
+        .with_language("de", "Titel").unwrap()),
+    ];
+    let mut chunks = Png::try_from(&PNG_FILE[..]).unwrap().chunks().to_vec();
+    let iend = chunks.pop().unwrap();
+    chunks.extend(texts.iter().map(Text::to_chunk));
+    chunks.push(iend);
+    let mut png = Png::try_from(Png::from_chunks(chunks).as_bytes().as_slice()).unwrap();
+
+    assert_eq!(png.text("Title").unwrap(), texts[2]);
+    // the first text with the keyword wins
+    assert_eq!(png.text("Comment").unwrap(), texts[0]);
+    assert!(matches!(png.text("Missing"), Err(PngError::KeywordNotFound { .. })));
+    assert!(matches!(png.remove_text("Missing"), Err(PngError::KeywordNotFound { .. })));
+
+    // a broken text leaves the png as it was
+    let broken = Chunk::new(ChunkType::try_from("zTXt").unwrap(), b"Comment\0\0not zlib".to_vec());
+    let mut with_broken = Png::from_chunks([png.chunks(), &[broken]].concat());
+    let before = with_broken.as_bytes();
+    assert!(matches!(with_broken.remove_text("Comment"), Err(PngError::InvalidChunkData { .. })));
+    assert_eq!(with_broken.as_bytes(), before);
+
+    // removing by keyword takes every text chunk type with it
+    assert_eq!(png.remove_text("Comment").unwrap(), texts[..2]);
+    assert_eq!(png.texts().unwrap(), texts[2..]);
+    assert!(png.validate().is_empty());
+  }
+
   #[test]
   fn test_chunk_by_type() {
     let png = testing_png();
@@ -265,11 +592,40 @@ mod tests {
     assert!(png.is_ok());
   }
 
+  #[test]
+  fn test_png_from_reader() {
+    let png = Png::from_reader(&PNG_FILE[..]).unwrap();
+    assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+  }
+
+  #[test]
+  fn test_write_to() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    assert_eq!(png.write_to(Vec::new()).unwrap(), PNG_FILE.to_vec());
+    assert!(testing_png().write_to(Vec::new()).is_err());
+  }
+
+  #[test]
+  fn test_header_info() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    let ihdr = png.header_info().unwrap();
+    assert_eq!((ihdr.width, ihdr.height), (50, 50));
+    assert!(matches!(testing_png().header_info(), Err(PngError::ChunkNotFound { .. })));
+  }
+
+  #[test]
+  fn test_image_data() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    // 50 rows of a filter byte and 50 rgba pixels
+    assert_eq!(png.image_data().unwrap().len(), 50 * (1 + 50 * 4));
+    assert!(matches!(testing_png().image_data(), Err(PngError::ChunkNotFound { .. })));
+  }
+
   #[test]
   fn test_as_bytes() {
     let png = Png::try_from(&PNG_FILE[..]).unwrap();
     let actual = png.as_bytes();
-    let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+    let expected: Vec<u8> = PNG_FILE.to_vec();
     assert_eq!(actual, expected);
   }
 
@@ -292,7 +648,7 @@ mod tests {
   }
 
   // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
-  const PNG_FILE: [u8; 4803] = [
+  pub(crate) const PNG_FILE: [u8; 4803] = [
     137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
     6, 0, 0, 0, 30, 63, 136, 177, 0, 0, 0, 1, 115, 82, 71, 66, 0, 174, 206, 28, 233, 0, 0, 0,
     4, 103, 65, 77, 65, 0, 0, 177, 143, 11, 252, 97, 5, 0, 0, 0, 9, 112, 72, 89, 115, 0, 0, 14,
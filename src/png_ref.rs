@@ -0,0 +1,89 @@
+use crate::chunk_ref::ChunkRef;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{Error, PngError, Result};
+
+/// A png whose chunks borrow their data from the input buffer, e.g. a file
+/// read into memory or mmapped. Nothing is copied, so listing the chunks of a
+/// large file is cheap. Use `to_png` to get an owned, editable `Png`.
+#[derive(Debug, Clone)]
+pub struct PngRef<'a> {
+  chunks: Vec<ChunkRef<'a>>,
+}
+
+impl<'a> PngRef<'a> {
+  /// Returns slice of chunks.
+  pub fn chunks(&self) -> &[ChunkRef<'a>] {
+    &self.chunks
+  }
+
+  /// Returns the first chunk with the given chunk type.
+  pub fn chunk_by_type(&self, chunk_type: &ChunkType) -> Option<&ChunkRef<'a>> {
+    self.chunks.iter().find(|c| c.chunk_type() == chunk_type)
+  }
+
+  /// Copies all chunks into an owned png.
+  pub fn to_png(&self) -> Png {
+    Png::from_chunks(self.chunks.iter().map(|c| c.to_chunk()).collect())
+  }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+  type Error = Error;
+
+  fn try_from(bytes: &'a [u8]) -> Result<Self> {
+    // too small or header not identifying png file
+    if bytes.len() < 8 || bytes[..8] != Png::STANDARD_HEADER {
+      return Err(PngError::InvalidSignature { found: bytes[..bytes.len().min(8)].to_vec() });
+    }
+
+    let mut chunks = Vec::<ChunkRef<'a>>::new();
+    // iterate over bytes and read one chunk at a time, the chunk parser does
+    // the bounds checks so a bad length field can't make us slice out of range
+    let mut cursor = 8; // loc of first byte to read
+    while cursor < bytes.len() {
+      let chunk = ChunkRef::from_bytes_at(&bytes[cursor..], chunks.len(), cursor)?;
+      // total chunk is length (4) + type (4) + data + crc (4) = 12 + length
+      cursor += 12 + chunk.length() as usize;
+      chunks.push(chunk);
+    }
+
+    Ok(PngRef { chunks })
+  }
+}
+
+impl<'a> From<&'a Png> for PngRef<'a> {
+  fn from(png: &'a Png) -> Self {
+    PngRef { chunks: png.chunks().iter().map(ChunkRef::from).collect() }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::png::tests::PNG_FILE;
+  use std::str::FromStr;
+
+  #[test]
+  fn test_png_ref_from_bytes() {
+    let png = PngRef::try_from(&PNG_FILE[..]).unwrap();
+    assert_eq!(png.chunks().len(), 7);
+
+    let idat = png.chunk_by_type(&ChunkType::from_str("IDAT").unwrap()).unwrap();
+    let start = idat.data().as_ptr() as usize - PNG_FILE.as_ptr() as usize;
+    assert_eq!(&PNG_FILE[start - 4..start], b"IDAT");
+  }
+
+  #[test]
+  fn test_png_ref_to_png() {
+    let png = PngRef::try_from(&PNG_FILE[..]).unwrap().to_png();
+    assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    assert_eq!(PngRef::from(&png).chunks().len(), 7);
+  }
+
+  #[test]
+  fn test_png_ref_errors() {
+    assert!(matches!(PngRef::try_from(&PNG_FILE[1..]), Err(PngError::InvalidSignature { .. })));
+    assert!(matches!(PngRef::try_from(&PNG_FILE[..100]), Err(PngError::TruncatedChunk { index: 4, .. })));
+  }
+}
@@ -0,0 +1,197 @@
+use std::str::FromStr;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::validate::{AFTER_PLTE, BEFORE_IDAT, BEFORE_PLTE, RESERVED_BIT};
+use crate::{Error, PngError, Result};
+
+/// Where a new chunk goes in a png, see `Png::insert_chunk_at`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Position {
+  /// Right before IEND, or at the end if there is none.
+  BeforeIend,
+  /// Right after IHDR.
+  AfterIhdr,
+  /// Right before the first IDAT.
+  BeforeFirstIdat,
+  /// Right after the last IDAT.
+  AfterLastIdat,
+  /// At this index, the chunk that was there moves back.
+  Index(usize),
+  /// Right before the first chunk of this type.
+  Before(ChunkType),
+  /// Right after the last chunk of this type.
+  After(ChunkType),
+}
+
+impl Position {
+  /// The index the new chunk gets in `chunks`.
+  pub fn index_in(&self, chunks: &[Chunk]) -> Result<usize> {
+    let first = |chunk_type: &[u8; 4]| chunks.iter().position(|c| c.chunk_type().bytes() == *chunk_type);
+    let last = |chunk_type: &[u8; 4]| chunks.iter().rposition(|c| c.chunk_type().bytes() == *chunk_type);
+    let not_found = |chunk_type: &str| PngError::ChunkNotFound { chunk_type: chunk_type.to_string() };
+
+    match self {
+      Position::BeforeIend => Ok(first(b"IEND").unwrap_or(chunks.len())),
+      Position::AfterIhdr => first(b"IHDR").map(|i| i + 1).ok_or_else(|| not_found("IHDR")),
+      Position::BeforeFirstIdat => first(b"IDAT").ok_or_else(|| not_found("IDAT")),
+      Position::AfterLastIdat => last(b"IDAT").map(|i| i + 1).ok_or_else(|| not_found("IDAT")),
+      Position::Index(index) if *index <= chunks.len() => Ok(*index),
+      Position::Index(index) => Err(PngError::InvalidPosition {
+        position: index.to_string(),
+        reason: format!("the png has only {} chunks", chunks.len()),
+      }),
+      Position::Before(chunk_type) => first(&chunk_type.bytes()).ok_or_else(|| not_found(&chunk_type.to_string())),
+      Position::After(chunk_type) => last(&chunk_type.bytes()).map(|i| i + 1).ok_or_else(|| not_found(&chunk_type.to_string())),
+    }
+  }
+}
+
+/// Checks that a chunk of `chunk_type` may be inserted into `chunks` at
+/// `index` without breaking the rules `validate::validate` checks.
+pub fn check_placement(chunk_type: &ChunkType, chunks: &[Chunk], index: usize) -> Result<()> {
+  let bytes = chunk_type.bytes();
+  let refuse = |reason: &str| Err(PngError::InvalidChunkOrder {
+    index,
+    chunk_type: chunk_type.to_string(),
+    reason: reason.to_string(),
+  });
+  let first = |chunk_type: &[u8; 4]| chunks.iter().position(|c| c.chunk_type().bytes() == *chunk_type);
+  let last = |chunk_type: &[u8; 4]| chunks.iter().rposition(|c| c.chunk_type().bytes() == *chunk_type);
+
+  if index > chunks.len() {
+    return refuse("index is past the end");
+  }
+  if !chunk_type.is_reserved_bit_valid() {
+    return refuse(&format!("{} {}", chunk_type, RESERVED_BIT));
+  }
+  if &bytes == b"IHDR" || &bytes == b"IEND" {
+    return refuse("IHDR and IEND can't be inserted, there is exactly one of each");
+  }
+  if first(b"IHDR").is_some_and(|ihdr| index <= ihdr) {
+    return refuse("nothing may come before IHDR");
+  }
+  if last(b"IEND").is_some_and(|iend| index > iend) {
+    return refuse("nothing may come after IEND");
+  }
+  if let (Some(first_idat), Some(last_idat)) = (first(b"IDAT"), last(b"IDAT")) {
+    if &bytes == b"IDAT" {
+      if index < first_idat || index > last_idat + 1 {
+        return refuse("IDAT chunks must be consecutive");
+      }
+    } else if index > first_idat && index <= last_idat {
+      return refuse("IDAT chunks must be consecutive");
+    }
+    if BEFORE_IDAT.contains(&&bytes) && index > first_idat {
+      return refuse("must come before the first IDAT");
+    }
+  }
+  if let Some(plte) = first(b"PLTE") {
+    if BEFORE_PLTE.contains(&&bytes) && index > plte {
+      return refuse("must come before PLTE");
+    }
+    if AFTER_PLTE.contains(&&bytes) && index <= plte {
+      return refuse("must come after PLTE");
+    }
+    if &bytes == b"IDAT" && index <= plte {
+      return refuse("must come after PLTE");
+    }
+  }
+  Ok(())
+}
+
+/// Parses "before-iend", "after-ihdr", "before-idat", "after-idat", an
+/// index, "before:TYPE" or "after:TYPE".
+impl FromStr for Position {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let invalid = || PngError::InvalidPosition {
+      position: s.to_string(),
+      reason: "expected before-iend, after-ihdr, before-idat, after-idat, an index, before:TYPE or after:TYPE".to_string(),
+    };
+    match s {
+      "before-iend" => Ok(Position::BeforeIend),
+      "after-ihdr" => Ok(Position::AfterIhdr),
+      "before-idat" => Ok(Position::BeforeFirstIdat),
+      "after-idat" => Ok(Position::AfterLastIdat),
+      _ => {
+        if let Some(chunk_type) = s.strip_prefix("before:") {
+          Ok(Position::Before(ChunkType::from_str(chunk_type)?))
+        } else if let Some(chunk_type) = s.strip_prefix("after:") {
+          Ok(Position::After(ChunkType::from_str(chunk_type)?))
+        } else {
+          s.parse().map(Position::Index).map_err(|_| invalid())
+        }
+      },
+    }
+  }
+}
+
+impl std::fmt::Display for Position {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Position::BeforeIend => write!(f, "before-iend"),
+      Position::AfterIhdr => write!(f, "after-ihdr"),
+      Position::BeforeFirstIdat => write!(f, "before-idat"),
+      Position::AfterLastIdat => write!(f, "after-idat"),
+      Position::Index(index) => write!(f, "{}", index),
+      Position::Before(chunk_type) => write!(f, "before:{}", chunk_type),
+      Position::After(chunk_type) => write!(f, "after:{}", chunk_type),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn chunks(types: &[&str]) -> Vec<Chunk> {
+    types.iter().map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), vec![])).collect()
+  }
+
+  #[test]
+  fn test_index_in() {
+    let chunks = chunks(&["IHDR", "gAMA", "IDAT", "IDAT", "tEXt", "IEND"]);
+    assert_eq!(Position::BeforeIend.index_in(&chunks).unwrap(), 5);
+    assert_eq!(Position::AfterIhdr.index_in(&chunks).unwrap(), 1);
+    assert_eq!(Position::BeforeFirstIdat.index_in(&chunks).unwrap(), 2);
+    assert_eq!(Position::AfterLastIdat.index_in(&chunks).unwrap(), 4);
+    assert_eq!(Position::Index(6).index_in(&chunks).unwrap(), 6);
+    assert!(Position::Index(7).index_in(&chunks).is_err());
+    assert_eq!(Position::Before(ChunkType::from_str("IDAT").unwrap()).index_in(&chunks).unwrap(), 2);
+    assert_eq!(Position::After(ChunkType::from_str("IDAT").unwrap()).index_in(&chunks).unwrap(), 4);
+    assert!(Position::After(ChunkType::from_str("PLTE").unwrap()).index_in(&chunks).is_err());
+    assert_eq!(Position::BeforeIend.index_in(&chunks[..2]).unwrap(), 2);
+  }
+
+  #[test]
+  fn test_check_placement() {
+    let chunks = chunks(&["IHDR", "gAMA", "PLTE", "IDAT", "IDAT", "tEXt", "IEND"]);
+    let check = |chunk_type: &str, index| check_placement(&ChunkType::from_str(chunk_type).unwrap(), &chunks, index);
+    assert!(check("tEXt", 1).is_ok());
+    assert!(check("tEXt", 6).is_ok());
+    assert!(check("tEXt", 0).is_err());
+    assert!(check("tEXt", 7).is_err());
+    assert!(check("tEXt", 4).is_err());
+    assert!(check("IDAT", 5).is_ok());
+    assert!(check("IDAT", 6).is_err());
+    assert!(check("IEND", 6).is_err());
+    assert!(check("pHYs", 3).is_ok());
+    assert!(check("pHYs", 5).is_err());
+    assert!(check("sRGB", 2).is_ok());
+    assert!(check("sRGB", 3).is_err());
+    assert!(check("tRNS", 2).is_err());
+    assert!(check("tRNS", 3).is_ok());
+    assert!(check("Rust", 6).is_err());
+  }
+
+  #[test]
+  fn test_parse() {
+    for s in ["before-iend", "after-ihdr", "before-idat", "after-idat", "3", "before:tEXt", "after:IDAT"] {
+      assert_eq!(Position::from_str(s).unwrap().to_string(), s);
+    }
+    assert!(Position::from_str("middle").is_err());
+    assert!(Position::from_str("after:ID").is_err());
+  }
+}
@@ -0,0 +1,173 @@
+use std::io::{ErrorKind, Read};
+
+use crate::chunk::{Chunk, array4_from_slice};
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{PngError, Result};
+
+/// Reads a png from any `Read` one chunk at a time, without buffering the
+/// whole file. The signature is checked on creation, after that the reader
+/// is an iterator over the chunks. Every crc is checked while reading and
+/// the iterator stops after the first error.
+pub struct PngReader<R: Read> {
+  reader: R,
+  index: usize,
+  offset: usize,
+  done: bool,
+}
+
+impl<R: Read> PngReader<R> {
+  /// Creates a reader and checks the png signature.
+  pub fn new(mut reader: R) -> Result<PngReader<R>> {
+    let mut header = [0; 8];
+    let read = read_full(&mut reader, &mut header)?;
+    if read < 8 || header != Png::STANDARD_HEADER {
+      return Err(PngError::InvalidSignature { found: header[..read].to_vec() });
+    }
+
+    Ok(PngReader {
+      reader,
+      index: 0,
+      offset: 8,
+      done: false,
+    })
+  }
+
+  /// Returns the offset in the file of the next chunk to read.
+  pub fn offset(&self) -> usize {
+    self.offset
+  }
+
+  /// Returns the underlying reader.
+  pub fn into_inner(self) -> R {
+    self.reader
+  }
+
+  /// Reads the next chunk, `None` if the input ends right after a chunk.
+  fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+    let (index, offset) = (self.index, self.offset);
+
+    // length (4) + type (4)
+    let mut head = [0; 8];
+    let read = read_full(&mut self.reader, &mut head)?;
+    if read == 0 {
+      return Ok(None);
+    }
+    if read < 8 {
+      return Err(PngError::TruncatedChunk { index, offset, needed: 12, available: read });
+    }
+
+    let length = u32::from_be_bytes(array4_from_slice(&head[..4]));
+    if length > Chunk::MAX_LENGTH {
+      return Err(PngError::ChunkTooLong { index, offset, length });
+    }
+    let total = length as usize + 12;
+
+    // don't trust the length field for the allocation, let the vec grow
+    // with what is actually there
+    let mut data = Vec::new();
+    (&mut self.reader).take(length as u64).read_to_end(&mut data)?;
+    let mut crc = [0; 4];
+    let read = if data.len() == length as usize { read_full(&mut self.reader, &mut crc)? } else { 0 };
+    if data.len() < length as usize || read < 4 {
+      return Err(PngError::TruncatedChunk { index, offset, needed: total, available: 8 + data.len() + read });
+    }
+
+    let crc = u32::from_be_bytes(crc);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&head[4..]);
+    hasher.update(&data);
+    let checksum = hasher.finalize();
+    if checksum != crc {
+      return Err(PngError::CrcMismatch { index, offset, expected: crc, actual: checksum });
+    }
+
+    let chunk_type = ChunkType::try_from(array4_from_slice(&head[4..])).map_err(|_| {
+      PngError::InvalidChunkType { bytes: head[4..].to_vec(), index: Some(index), offset: Some(offset) }
+    })?;
+
+    self.index += 1;
+    self.offset += total;
+    Ok(Some(Chunk::from_checked_parts(chunk_type, data, crc)))
+  }
+}
+
+impl<R: Read> Iterator for PngReader<R> {
+  type Item = Result<Chunk>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+    match self.read_chunk() {
+      Ok(Some(chunk)) => Some(Ok(chunk)),
+      Ok(None) => {
+        self.done = true;
+        None
+      },
+      Err(err) => {
+        self.done = true;
+        Some(Err(err))
+      },
+    }
+  }
+}
+
+/// Reads until `buf` is full or the input ends, returns the number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+  let mut read = 0;
+  while read < buf.len() {
+    match reader.read(&mut buf[read..]) {
+      Ok(0) => break,
+      Ok(n) => read += n,
+      Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+      Err(err) => return Err(err.into()),
+    }
+  }
+  Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::png::tests::PNG_FILE;
+
+  #[test]
+  fn test_read_chunks() {
+    let reader = PngReader::new(&PNG_FILE[..]).unwrap();
+    let types: Vec<String> = reader.map(|c| c.unwrap().chunk_type().to_string()).collect();
+    assert_eq!(types, vec!["IHDR", "sRGB", "gAMA", "pHYs", "IDAT", "RuSt", "IEND"]);
+  }
+
+  #[test]
+  fn test_offsets() {
+    let mut reader = PngReader::new(&PNG_FILE[..]).unwrap();
+    assert_eq!(reader.offset(), 8);
+    reader.next().unwrap().unwrap();
+    assert_eq!(reader.offset(), 8 + 12 + 13);
+  }
+
+  #[test]
+  fn test_invalid_signature() {
+    assert!(matches!(PngReader::new(&PNG_FILE[1..]), Err(PngError::InvalidSignature { .. })));
+    assert!(matches!(PngReader::new(&PNG_FILE[..3]), Err(PngError::InvalidSignature { .. })));
+  }
+
+  #[test]
+  fn test_crc_mismatch_stops_iteration() {
+    let mut bytes = PNG_FILE.to_vec();
+    // first data byte of the sRGB chunk
+    bytes[41] ^= 0xff;
+    let mut reader = PngReader::new(bytes.as_slice()).unwrap();
+    assert!(reader.next().unwrap().is_ok());
+    assert!(matches!(reader.next(), Some(Err(PngError::CrcMismatch { index: 1, offset: 33, .. }))));
+    assert!(reader.next().is_none());
+  }
+
+  #[test]
+  fn test_truncated_input() {
+    let reader = PngReader::new(&PNG_FILE[..PNG_FILE.len() - 2]).unwrap();
+    let last = reader.last().unwrap();
+    assert!(matches!(last, Err(PngError::TruncatedChunk { index: 6, needed: 12, available: 10, .. })));
+  }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use crate::chunk::{Chunk, array4_from_slice};
+use crate::chunk_type::ChunkType;
+use crate::{PngError, Result};
+
+/// Marks the data of a chunk as one piece of a split payload.
+pub const MAGIC: [u8; 4] = [0x89, b'P', b'R', b'T'];
+
+/// Magic, index, total and the crc of the whole payload, 4 bytes each.
+pub const HEADER_LEN: usize = 16;
+
+/// Chunk size used by encode unless asked otherwise, well below what
+/// decoders choke on.
+pub const DEFAULT_MAX_LEN: usize = 1 << 20;
+
+/// Stores `data` in chunks of `chunk_type` with at most `max_len` bytes of
+/// data each. Data that fits is a single plain chunk, otherwise every piece
+/// starts with a header saying which piece of how many it is.
+pub fn split(chunk_type: &ChunkType, data: &[u8], max_len: usize) -> Result<Vec<Chunk>> {
+  if max_len <= HEADER_LEN || max_len > Chunk::MAX_LENGTH as usize {
+    return Err(PngError::InvalidInput {
+      reason: format!("chunk size must be between {} and {}, got {}", HEADER_LEN + 1, Chunk::MAX_LENGTH, max_len),
+    });
+  }
+  if data.len() <= max_len {
+    return Ok(vec![Chunk::new(chunk_type.clone(), data.to_vec())]);
+  }
+
+  let pieces: Vec<&[u8]> = data.chunks(max_len - HEADER_LEN).collect();
+  let total = u32::try_from(pieces.len())
+    .map_err(|_| PngError::InvalidInput { reason: format!("{} pieces is too many, use larger chunks", pieces.len()) })?;
+  let crc = crc32fast::hash(data);
+  Ok(pieces.iter().enumerate().map(|(index, piece)| {
+    let mut chunk_data = Vec::with_capacity(HEADER_LEN + piece.len());
+    chunk_data.extend_from_slice(&MAGIC);
+    chunk_data.extend_from_slice(&(index as u32).to_be_bytes());
+    chunk_data.extend_from_slice(&total.to_be_bytes());
+    chunk_data.extend_from_slice(&crc.to_be_bytes());
+    chunk_data.extend_from_slice(piece);
+    Chunk::new(chunk_type.clone(), chunk_data)
+  }).collect())
+}
+
+/// Whether the chunk is a piece made by `split`.
+pub fn is_piece(chunk: &Chunk) -> bool {
+  chunk.data().len() >= HEADER_LEN && chunk.data()[..4] == MAGIC
+}
+
+/// A payload put back together from its pieces.
+#[derive(Debug)]
+pub struct Joined {
+  pub data: Vec<u8>,
+  /// Index of every piece in the order they are in the file.
+  pub order: Vec<u32>,
+}
+
+impl Joined {
+  pub fn in_order(&self) -> bool {
+    self.order.windows(2).all(|w| w[0] < w[1])
+  }
+}
+
+/// Joins pieces made by `split` by their index, whatever order they are in.
+/// Fails if pieces are missing or twice there, or if the result doesn't
+/// match the crc in the headers.
+pub fn join(pieces: &[&Chunk]) -> Result<Joined> {
+  let Some(first) = pieces.first() else {
+    return Err(PngError::ChunkNotFound { chunk_type: "split payload".to_string() });
+  };
+  let chunk_type = first.chunk_type().to_string();
+  let invalid = |reason: String| PngError::InvalidChunkData { chunk_type: chunk_type.clone(), reason };
+  let field = |chunk: &Chunk, i: usize| u32::from_be_bytes(array4_from_slice(&chunk.data()[i..i + 4]));
+
+  if let Some(chunk) = pieces.iter().find(|c| !is_piece(c)) {
+    return Err(invalid(format!("{} bytes of data without a piece header", chunk.length())));
+  }
+  let (total, crc) = (field(first, 8), field(first, 12));
+  if pieces.iter().any(|c| field(c, 8) != total || field(c, 12) != crc) {
+    return Err(invalid("pieces of different payloads are mixed".to_string()));
+  }
+
+  let order: Vec<u32> = pieces.iter().map(|c| field(c, 4)).collect();
+  // a map, the total comes from the file and may be huge
+  let mut by_index = BTreeMap::new();
+  for (chunk, &index) in pieces.iter().zip(&order) {
+    if index >= total {
+      return Err(invalid(format!("piece {} of {} is out of range", index, total)));
+    }
+    if by_index.insert(index, &chunk.data()[HEADER_LEN..]).is_some() {
+      return Err(invalid(format!("piece {} is there twice", index)));
+    }
+  }
+  let count = total as usize - by_index.len();
+  if count > 0 {
+    let missing: Vec<String> = (0..total).filter(|i| !by_index.contains_key(i)).take(10).map(|i| i.to_string()).collect();
+    return Err(invalid(format!("{} of {} pieces are missing: {}{}", count, total, missing.join(", "), if count > 10 { ", ..." } else { "" })));
+  }
+
+  let data: Vec<u8> = by_index.into_values().flatten().copied().collect();
+  if crc32fast::hash(&data) != crc {
+    return Err(invalid("joined pieces don't match the crc of the payload".to_string()));
+  }
+  Ok(Joined { data, order })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  fn testing_data() -> Vec<u8> {
+    (0..1000u32).map(|i| (i % 251) as u8).collect()
+  }
+
+  fn testing_pieces() -> Vec<Chunk> {
+    split(&ChunkType::from_str("ruSt").unwrap(), &testing_data(), 316).unwrap()
+  }
+
+  #[test]
+  fn test_split_and_join() {
+    let pieces = testing_pieces();
+    assert_eq!(pieces.len(), 4);
+    assert!(pieces.iter().all(|c| c.length() <= 316 && is_piece(c)));
+    let joined = join(&pieces.iter().collect::<Vec<_>>()).unwrap();
+    assert_eq!(joined.data, testing_data());
+    assert!(joined.in_order());
+
+    let small = split(&ChunkType::from_str("ruSt").unwrap(), b"small", 316).unwrap();
+    assert_eq!(small.len(), 1);
+    assert!(!is_piece(&small[0]));
+    assert!(split(&ChunkType::from_str("ruSt").unwrap(), b"small", HEADER_LEN).is_err());
+  }
+
+  #[test]
+  fn test_join_out_of_order() {
+    let pieces = testing_pieces();
+    let joined = join(&[&pieces[2], &pieces[0], &pieces[3], &pieces[1]]).unwrap();
+    assert_eq!(joined.data, testing_data());
+    assert_eq!(joined.order, vec![2, 0, 3, 1]);
+    assert!(!joined.in_order());
+  }
+
+  #[test]
+  fn test_join_broken() {
+    let pieces = testing_pieces();
+    let error = |pieces: &[&Chunk]| join(pieces).unwrap_err().to_string();
+    assert_eq!(error(&[&pieces[0], &pieces[3]]), "invalid ruSt chunk: 2 of 4 pieces are missing: 1, 2");
+    assert_eq!(error(&[&pieces[0], &pieces[1], &pieces[1]]), "invalid ruSt chunk: piece 1 is there twice");
+
+    let mut other = pieces[1].clone();
+    let mut data = other.data().to_vec();
+    data[HEADER_LEN] ^= 1;
+    other.set_data(data).unwrap();
+    assert_eq!(error(&[&pieces[0], &other, &pieces[2], &pieces[3]]), "invalid ruSt chunk: joined pieces don't match the crc of the payload");
+    assert!(join(&[]).is_err());
+  }
+}
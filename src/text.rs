@@ -0,0 +1,472 @@
+use std::str::FromStr;
+
+use crate::chunk::{Chunk, check_type, invalid};
+use crate::chunk_type::ChunkType;
+use crate::{zlib, Error, PngError, Result};
+
+/// Longest keyword the spec allows, in characters.
+pub const MAX_KEYWORD_LEN: usize = 79;
+
+/// Texts longer than this many bytes are compressed by `Text::new` and
+/// `InternationalTextChunk::new`.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Compressed texts may inflate to at most this many bytes, so a small
+/// chunk can't fill memory. Longer texts can't be compressed.
+pub const MAX_COMPRESSED_TEXT_LEN: usize = 8 << 20;
+
+/// Checks a keyword: 1 to 79 printable Latin-1 characters, no leading,
+/// trailing or consecutive spaces.
+pub fn validate_keyword(keyword: &str) -> Result<()> {
+  let invalid = |reason: String| invalid("tEXt", format!("keyword {:?} {}", keyword, reason));
+  let len = keyword.chars().count();
+  if len == 0 || len > MAX_KEYWORD_LEN {
+    return Err(invalid(format!("has {} characters, must be 1 to {}", len, MAX_KEYWORD_LEN)));
+  }
+  if let Some(c) = keyword.chars().find(|&c| !matches!(c as u32, 32..=126 | 161..=255)) {
+    return Err(invalid(format!("contains {:?}, only printable Latin-1 is allowed", c)));
+  }
+  if keyword.starts_with(' ') || keyword.ends_with(' ') {
+    return Err(invalid("has leading or trailing spaces".to_string()));
+  }
+  if keyword.contains("  ") {
+    return Err(invalid("has consecutive spaces".to_string()));
+  }
+  Ok(())
+}
+
+/// A tEXt chunk, uncompressed Latin-1 text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+  keyword: String,
+  text: String,
+}
+
+impl TextChunk {
+  /// Creates a tEXt chunk, the text must be Latin-1 without nul characters.
+  pub fn new(keyword: &str, text: &str) -> Result<TextChunk> {
+    validate_keyword(keyword)?;
+    check_latin1("tEXt", text)?;
+    Ok(TextChunk { keyword: keyword.to_string(), text: text.to_string() })
+  }
+
+  /// Returns the keyword.
+  pub fn keyword(&self) -> &str {
+    &self.keyword
+  }
+
+  /// Returns the text.
+  pub fn text(&self) -> &str {
+    &self.text
+  }
+
+  /// Serializes the text into a tEXt chunk.
+  pub fn to_chunk(&self) -> Chunk {
+    let data = [latin1_encode(&self.keyword), vec![0], latin1_encode(&self.text)].concat();
+    Chunk::new(ChunkType::from_str("tEXt").unwrap(), data)
+  }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    check_type(chunk, "tEXt")?;
+    let (keyword, text) = split_keyword("tEXt", chunk.data())?;
+    Ok(TextChunk { keyword, text: latin1_decode(text) })
+  }
+}
+
+/// A zTXt chunk, zlib compressed Latin-1 text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedTextChunk {
+  keyword: String,
+  text: String,
+}
+
+impl CompressedTextChunk {
+  /// Creates a zTXt chunk, the text must be Latin-1 without nul characters.
+  pub fn new(keyword: &str, text: &str) -> Result<CompressedTextChunk> {
+    validate_keyword(keyword)?;
+    check_latin1("zTXt", text)?;
+    check_compressed_len("zTXt", text)?;
+    Ok(CompressedTextChunk { keyword: keyword.to_string(), text: text.to_string() })
+  }
+
+  /// Returns the keyword.
+  pub fn keyword(&self) -> &str {
+    &self.keyword
+  }
+
+  /// Returns the text.
+  pub fn text(&self) -> &str {
+    &self.text
+  }
+
+  /// Compresses the text into a zTXt chunk.
+  pub fn to_chunk(&self) -> Chunk {
+    let compressed = zlib::deflate(&latin1_encode(&self.text), 9);
+    let data = [latin1_encode(&self.keyword), vec![0, 0], compressed].concat();
+    Chunk::new(ChunkType::from_str("zTXt").unwrap(), data)
+  }
+}
+
+impl TryFrom<&Chunk> for CompressedTextChunk {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    check_type(chunk, "zTXt")?;
+    let (keyword, rest) = split_keyword("zTXt", chunk.data())?;
+    let (&method, compressed) = rest.split_first()
+      .ok_or_else(|| invalid("zTXt", "compression method is missing"))?;
+    let text = inflate("zTXt", method, compressed)?;
+    Ok(CompressedTextChunk { keyword, text: latin1_decode(&text) })
+  }
+}
+
+/// An iTXt chunk, UTF-8 text that may be compressed, with an optional
+/// language tag and the keyword translated into that language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalTextChunk {
+  keyword: String,
+  compressed: bool,
+  language_tag: String,
+  translated_keyword: String,
+  text: String,
+}
+
+impl InternationalTextChunk {
+  /// Creates an iTXt chunk without language, compressed if the text is
+  /// longer than `COMPRESSION_THRESHOLD` but not longer than
+  /// `MAX_COMPRESSED_TEXT_LEN`.
+  pub fn new(keyword: &str, text: &str) -> Result<InternationalTextChunk> {
+    validate_keyword(keyword)?;
+    Ok(InternationalTextChunk {
+      keyword: keyword.to_string(),
+      compressed: compressible(text),
+      language_tag: String::new(),
+      translated_keyword: String::new(),
+      text: text.to_string(),
+    })
+  }
+
+  /// Sets the language tag, e.g. "en" or "x-klingon", and the keyword
+  /// translated into that language. Either can be empty.
+  pub fn with_language(mut self, language_tag: &str, translated_keyword: &str) -> Result<InternationalTextChunk> {
+    if let Some(c) = language_tag.chars().find(|&c| !(c.is_ascii_alphanumeric() || c == '-')) {
+      return Err(invalid("iTXt", format!("language tag {:?} contains {:?}", language_tag, c)));
+    }
+    if translated_keyword.contains('\0') {
+      return Err(invalid("iTXt", "translated keyword contains a nul character"));
+    }
+    self.language_tag = language_tag.to_string();
+    self.translated_keyword = translated_keyword.to_string();
+    Ok(self)
+  }
+
+  /// Forces compression on or off.
+  pub fn with_compression(mut self, compressed: bool) -> Result<InternationalTextChunk> {
+    if compressed {
+      check_compressed_len("iTXt", &self.text)?;
+    }
+    self.compressed = compressed;
+    Ok(self)
+  }
+
+  /// Returns the keyword.
+  pub fn keyword(&self) -> &str {
+    &self.keyword
+  }
+
+  /// Returns true if the text is stored compressed.
+  pub fn is_compressed(&self) -> bool {
+    self.compressed
+  }
+
+  /// Returns the language tag, empty if there is none.
+  pub fn language_tag(&self) -> &str {
+    &self.language_tag
+  }
+
+  /// Returns the keyword in the language of the text.
+  pub fn translated_keyword(&self) -> &str {
+    &self.translated_keyword
+  }
+
+  /// Returns the text.
+  pub fn text(&self) -> &str {
+    &self.text
+  }
+
+  /// Serializes the text into an iTXt chunk, compressing it if asked to.
+  pub fn to_chunk(&self) -> Chunk {
+    let text = if self.compressed {
+      zlib::deflate(self.text.as_bytes(), 9)
+    } else {
+      self.text.as_bytes().to_vec()
+    };
+    let data = [
+      latin1_encode(&self.keyword), vec![0, u8::from(self.compressed), 0],
+      self.language_tag.as_bytes().to_vec(), vec![0],
+      self.translated_keyword.as_bytes().to_vec(), vec![0],
+      text,
+    ].concat();
+    Chunk::new(ChunkType::from_str("iTXt").unwrap(), data)
+  }
+}
+
+impl TryFrom<&Chunk> for InternationalTextChunk {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    check_type(chunk, "iTXt")?;
+    let (keyword, rest) = split_keyword("iTXt", chunk.data())?;
+    let [flag, method, rest @ ..] = rest else {
+      return Err(invalid("iTXt", "compression flag and method are missing"));
+    };
+    let (language_tag, rest) = split_nul("iTXt", rest, "language tag")?;
+    let (translated_keyword, text) = split_nul("iTXt", rest, "translated keyword")?;
+    let text = match flag {
+      0 => text.to_vec(),
+      1 => inflate("iTXt", *method, text)?,
+      _ => return Err(invalid("iTXt", format!("unknown compression flag {}", flag))),
+    };
+
+    let utf8 = |bytes: Vec<u8>| String::from_utf8(bytes).map_err(|err| PngError::InvalidUtf8 {
+      chunk_type: "iTXt".to_string(),
+      valid_up_to: err.utf8_error().valid_up_to(),
+    });
+    Ok(InternationalTextChunk {
+      keyword,
+      compressed: *flag == 1,
+      language_tag: utf8(language_tag.to_vec())?,
+      translated_keyword: utf8(translated_keyword.to_vec())?,
+      text: utf8(text)?,
+    })
+  }
+}
+
+/// Any of the three text chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Text {
+  Plain(TextChunk),
+  Compressed(CompressedTextChunk),
+  International(InternationalTextChunk),
+}
+
+impl Text {
+  /// Chunk types that hold text.
+  pub const CHUNK_TYPES: [&'static str; 3] = ["tEXt", "zTXt", "iTXt"];
+
+  /// Picks the smallest chunk type that can hold the text: tEXt or zTXt for
+  /// Latin-1, iTXt otherwise. Texts longer than `COMPRESSION_THRESHOLD` are
+  /// compressed, unless they are too long to be inflated again.
+  pub fn new(keyword: &str, text: &str) -> Result<Text> {
+    if latin1_fits(text) {
+      if compressible(text) {
+        Ok(Text::Compressed(CompressedTextChunk::new(keyword, text)?))
+      } else {
+        Ok(Text::Plain(TextChunk::new(keyword, text)?))
+      }
+    } else {
+      Ok(Text::International(InternationalTextChunk::new(keyword, text)?))
+    }
+  }
+
+  /// Returns the keyword.
+  pub fn keyword(&self) -> &str {
+    match self {
+      Text::Plain(t) => t.keyword(),
+      Text::Compressed(t) => t.keyword(),
+      Text::International(t) => t.keyword(),
+    }
+  }
+
+  /// Returns the text.
+  pub fn text(&self) -> &str {
+    match self {
+      Text::Plain(t) => t.text(),
+      Text::Compressed(t) => t.text(),
+      Text::International(t) => t.text(),
+    }
+  }
+
+  /// Serializes the text into a chunk of its type.
+  pub fn to_chunk(&self) -> Chunk {
+    match self {
+      Text::Plain(t) => t.to_chunk(),
+      Text::Compressed(t) => t.to_chunk(),
+      Text::International(t) => t.to_chunk(),
+    }
+  }
+}
+
+impl TryFrom<&Chunk> for Text {
+  type Error = Error;
+
+  fn try_from(chunk: &Chunk) -> Result<Self> {
+    match &chunk.chunk_type().bytes() {
+      b"tEXt" => Ok(Text::Plain(TextChunk::try_from(chunk)?)),
+      b"zTXt" => Ok(Text::Compressed(CompressedTextChunk::try_from(chunk)?)),
+      b"iTXt" => Ok(Text::International(InternationalTextChunk::try_from(chunk)?)),
+      _ => Err(invalid("tEXt", format!("{} is not a text chunk", chunk.chunk_type()))),
+    }
+  }
+}
+
+/// Returns the keyword of a tEXt, zTXt or iTXt chunk without
+/// decompressing the text.
+pub fn keyword(chunk: &Chunk) -> Result<String> {
+  let chunk_type = chunk.chunk_type().to_string();
+  if !Text::CHUNK_TYPES.contains(&chunk_type.as_str()) {
+    return Err(invalid("tEXt", format!("{} is not a text chunk", chunk_type)));
+  }
+  split_keyword(&chunk_type, chunk.data()).map(|(keyword, _)| keyword)
+}
+
+/// Splits the nul separated keyword off and validates it.
+fn split_keyword<'a>(chunk_type: &str, data: &'a [u8]) -> Result<(String, &'a [u8])> {
+  let (keyword, rest) = split_nul(chunk_type, data, "keyword")?;
+  let keyword = latin1_decode(keyword);
+  validate_keyword(&keyword).map_err(|err| match err {
+    PngError::InvalidChunkData { reason, .. } => invalid(chunk_type, reason),
+    err => err,
+  })?;
+  Ok((keyword, rest))
+}
+
+fn split_nul<'a>(chunk_type: &str, data: &'a [u8], field: &str) -> Result<(&'a [u8], &'a [u8])> {
+  let end = data.iter().position(|&b| b == 0)
+    .ok_or_else(|| invalid(chunk_type, format!("{} is not nul terminated", field)))?;
+  Ok((&data[..end], &data[end + 1..]))
+}
+
+fn inflate(chunk_type: &str, method: u8, compressed: &[u8]) -> Result<Vec<u8>> {
+  if method != 0 {
+    return Err(invalid(chunk_type, format!("unknown compression method {}", method)));
+  }
+  zlib::inflate_chunks_limited([(0, compressed)], MAX_COMPRESSED_TEXT_LEN).map_err(|err| match err {
+    PngError::InflateLimit { .. } => err,
+    err => invalid(chunk_type, err.to_string()),
+  })
+}
+
+fn check_compressed_len(chunk_type: &str, text: &str) -> Result<()> {
+  if text.len() > MAX_COMPRESSED_TEXT_LEN {
+    return Err(invalid(chunk_type, format!("text has {} bytes, at most {} can be compressed", text.len(), MAX_COMPRESSED_TEXT_LEN)));
+  }
+  Ok(())
+}
+
+/// Worth compressing, and short enough to be inflated again.
+fn compressible(text: &str) -> bool {
+  text.len() > COMPRESSION_THRESHOLD && text.len() <= MAX_COMPRESSED_TEXT_LEN
+}
+
+fn latin1_fits(text: &str) -> bool {
+  text.chars().all(|c| c != '\0' && (c as u32) < 256)
+}
+
+fn check_latin1(chunk_type: &str, text: &str) -> Result<()> {
+  if let Some(c) = text.chars().find(|&c| c == '\0' || c as u32 > 255) {
+    return Err(invalid(chunk_type, format!("text contains {:?}, only Latin-1 without nul is allowed", c)));
+  }
+  Ok(())
+}
+
+fn latin1_decode(bytes: &[u8]) -> String {
+  bytes.iter().map(|&b| b as char).collect()
+}
+
+/// Only called on validated strings, so every char fits in a byte.
+fn latin1_encode(text: &str) -> Vec<u8> {
+  text.chars().map(|c| c as u8).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_keyword_validation() {
+    assert!(validate_keyword("Title").is_ok());
+    assert!(validate_keyword("Größe").is_ok());
+    assert!(validate_keyword(&"k".repeat(79)).is_ok());
+    assert!(validate_keyword("").is_err());
+    assert!(validate_keyword(&"k".repeat(80)).is_err());
+    assert!(validate_keyword(" Title").is_err());
+    assert!(validate_keyword("Title ").is_err());
+    assert!(validate_keyword("Two  spaces").is_err());
+    assert!(validate_keyword("Tab\there").is_err());
+    assert!(validate_keyword("日本").is_err());
+  }
+
+  #[test]
+  fn test_text_round_trip() {
+    let text = TextChunk::new("Comment", "café").unwrap();
+    let chunk = text.to_chunk();
+    assert_eq!(chunk.data(), b"Comment\0caf\xe9");
+    assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+    assert!(TextChunk::new("Comment", "日本").is_err());
+    assert!(TextChunk::try_from(&Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"no nul".to_vec())).is_err());
+  }
+
+  #[test]
+  fn test_compressed_text_round_trip() {
+    let long = "a".repeat(5000);
+    let chunk = CompressedTextChunk::new("Comment", &long).unwrap().to_chunk();
+    assert!(chunk.length() < 100);
+    assert_eq!(CompressedTextChunk::try_from(&chunk).unwrap().text(), long);
+
+    let mut data = chunk.data().to_vec();
+    data[8] = 1;
+    let chunk = Chunk::new(ChunkType::from_str("zTXt").unwrap(), data);
+    assert!(CompressedTextChunk::try_from(&chunk).is_err());
+  }
+
+  #[test]
+  fn test_international_text_round_trip() {
+    let text = InternationalTextChunk::new("Title", "日本の写真").unwrap()
+      .with_language("ja", "タイトル").unwrap();
+    assert!(!text.is_compressed());
+    let parsed = InternationalTextChunk::try_from(&text.to_chunk()).unwrap();
+    assert_eq!(parsed, text);
+    assert_eq!(parsed.language_tag(), "ja");
+    assert_eq!(parsed.translated_keyword(), "タイトル");
+
+    let compressed = text.with_compression(true).unwrap();
+    assert_eq!(InternationalTextChunk::try_from(&compressed.to_chunk()).unwrap(), compressed);
+    assert!(InternationalTextChunk::new("Title", "x").unwrap().with_language("en us", "").is_err());
+  }
+
+  #[test]
+  fn test_text_picks_chunk_type() {
+    assert!(matches!(Text::new("k", "plain").unwrap(), Text::Plain(_)));
+    assert!(matches!(Text::new("k", &"x".repeat(2000)).unwrap(), Text::Compressed(_)));
+    assert!(matches!(Text::new("k", "ünïcödé ✓").unwrap(), Text::International(_)));
+    let text = Text::new("k", "ünïcödé ✓").unwrap();
+    assert_eq!(Text::try_from(&text.to_chunk()).unwrap(), text);
+    assert_eq!(keyword(&text.to_chunk()).unwrap(), "k");
+  }
+
+  #[test]
+  fn test_decompression_bomb() {
+    // a few kilobytes that inflate to more than the limit
+    let bomb = zlib::deflate(&vec![b'a'; MAX_COMPRESSED_TEXT_LEN + 1], 9);
+    assert!(bomb.len() < 20_000);
+    let ztxt = Chunk::new(ChunkType::from_str("zTXt").unwrap(), [b"Comment\0\0".to_vec(), bomb.clone()].concat());
+    let itxt = Chunk::new(ChunkType::from_str("iTXt").unwrap(), [b"Comment\0\x01\0\0\0".to_vec(), bomb].concat());
+    for chunk in [&ztxt, &itxt] {
+      assert!(matches!(Text::try_from(chunk), Err(PngError::InflateLimit { limit: MAX_COMPRESSED_TEXT_LEN })));
+      assert_eq!(keyword(chunk).unwrap(), "Comment");
+    }
+    // too long to compress, so it is stored as it is
+    let long = "a".repeat(MAX_COMPRESSED_TEXT_LEN + 1);
+    assert!(matches!(Text::new("k", &long).unwrap(), Text::Plain(_)));
+    assert!(CompressedTextChunk::new("k", &long).is_err());
+    let itxt = InternationalTextChunk::new("k", &long).unwrap();
+    assert!(!itxt.is_compressed());
+    assert!(itxt.with_compression(true).is_err());
+    assert!(InternationalTextChunk::new("k", &"a".repeat(COMPRESSION_THRESHOLD + 1)).unwrap().is_compressed());
+  }
+}
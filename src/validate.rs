@@ -0,0 +1,261 @@
+use crate::ancillary::Ancillary;
+use crate::chunk::{Chunk, invalid};
+use crate::decoder;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::inspect::Inspection;
+use crate::palette::{Palette, Transparency};
+use crate::{zlib, PngError};
+
+/// Chunks that must come before the first IDAT.
+pub(crate) const BEFORE_IDAT: [&[u8; 4]; 11] = [b"PLTE", b"tRNS", b"bKGD", b"hIST", b"gAMA", b"cHRM", b"iCCP", b"sRGB", b"sBIT", b"pHYs", b"sPLT"];
+/// Chunks that must come before PLTE.
+pub(crate) const BEFORE_PLTE: [&[u8; 4]; 5] = [b"gAMA", b"cHRM", b"iCCP", b"sRGB", b"sBIT"];
+/// Chunks that must come after PLTE.
+pub(crate) const AFTER_PLTE: [&[u8; 4]; 3] = [b"tRNS", b"bKGD", b"hIST"];
+/// Chunks that may appear at most once.
+pub(crate) const SINGLE: [&[u8; 4]; 13] = [b"IHDR", b"PLTE", b"IEND", b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"bKGD", b"hIST", b"tRNS", b"pHYs", b"tIME"];
+
+/// Why a chunk type with a lowercase third letter is refused.
+pub(crate) const RESERVED_BIT: &str = "has the reserved bit set, the third letter must be uppercase";
+
+/// Checks the chunk structure rules of the spec and the reserved bit of
+/// every chunk type, and returns every violation. An empty vector means the
+/// structure is fine. Chunk data is only looked at for IHDR and IEND.
+pub fn validate(chunks: &[Chunk]) -> Vec<PngError> {
+  let mut violations = Vec::new();
+  let types: Vec<[u8; 4]> = chunks.iter().map(|c| c.chunk_type().bytes()).collect();
+  let first = |chunk_type: &[u8; 4]| types.iter().position(|t| t == chunk_type);
+  let last = |chunk_type: &[u8; 4]| types.iter().rposition(|t| t == chunk_type);
+  let violation = |index: usize, reason: &str| PngError::StructureViolation {
+    index,
+    chunk_type: String::from_utf8_lossy(&types[index]).into_owned(),
+    reason: reason.to_string(),
+  };
+
+  for (index, chunk) in chunks.iter().enumerate() {
+    let chunk_type = chunk.chunk_type();
+    if chunk_type.bytes().iter().all(u8::is_ascii_alphabetic) && !chunk_type.is_reserved_bit_valid() {
+      violations.push(violation(index, RESERVED_BIT));
+    }
+  }
+
+  match first(b"IHDR") {
+    None => violations.push(PngError::ChunkNotFound { chunk_type: "IHDR".to_string() }),
+    Some(0) => {},
+    Some(index) => violations.push(violation(index, "must be the first chunk")),
+  }
+  match last(b"IEND") {
+    None => violations.push(PngError::ChunkNotFound { chunk_type: "IEND".to_string() }),
+    Some(index) => {
+      if index != chunks.len() - 1 {
+        violations.push(violation(index, "must be the last chunk"));
+      }
+      if !chunks[index].data().is_empty() {
+        violations.push(invalid("IEND", "must be empty"));
+      }
+    },
+  }
+  if first(b"IDAT").is_none() {
+    violations.push(PngError::ChunkNotFound { chunk_type: "IDAT".to_string() });
+  }
+
+  for single in SINGLE {
+    let count = types.iter().filter(|t| t == &single).count();
+    if count > 1 {
+      violations.push(violation(last(single).unwrap(), &format!("may appear only once, found {}", count)));
+    }
+  }
+
+  if let (Some(first_idat), Some(last_idat)) = (first(b"IDAT"), last(b"IDAT")) {
+    if let Some(index) = (first_idat..last_idat).find(|&i| &types[i] != b"IDAT") {
+      violations.push(violation(index, "is not allowed between IDAT chunks"));
+    }
+    for index in (first_idat..types.len()).filter(|&i| BEFORE_IDAT.contains(&&types[i])) {
+      violations.push(violation(index, "must come before the first IDAT"));
+    }
+  }
+  if let Some(plte) = first(b"PLTE") {
+    for (index, chunk_type) in types.iter().enumerate() {
+      if BEFORE_PLTE.contains(&chunk_type) && index > plte {
+        violations.push(violation(index, "must come before PLTE"));
+      }
+      if AFTER_PLTE.contains(&chunk_type) && index < plte {
+        violations.push(violation(index, "must come after PLTE"));
+      }
+    }
+  }
+
+  if let Some(ihdr) = first(b"IHDR") {
+    match Ihdr::try_from(&chunks[ihdr]) {
+      Ok(ihdr) => match (ihdr.color_type, first(b"PLTE")) {
+        (ColorType::Grayscale | ColorType::GrayscaleAlpha, Some(_)) =>
+          violations.push(invalid("PLTE", "not allowed for grayscale images")),
+        (ColorType::Indexed, None) =>
+          violations.push(PngError::ChunkNotFound { chunk_type: "PLTE".to_string() }),
+        _ => {},
+      },
+      Err(err) => violations.push(err),
+    }
+  }
+  violations
+}
+
+/// Checks a whole file the way pngcheck does and returns every problem:
+/// the signature, crcs, chunk types, the rules of `validate`, the data of
+/// IHDR, PLTE, tRNS and the ancillary chunks, and that the image data
+/// inflates to the size the header asks for. Never panics and never
+/// inflates more than the header asks for, so it is safe to run on
+/// untrusted input.
+pub fn check_bytes(bytes: &[u8]) -> Vec<PngError> {
+  let inspection = Inspection::new(bytes);
+  let (header, palette) = (inspection.header(), inspection.palette());
+  let mut problems = Vec::new();
+  if !inspection.signature_ok() {
+    problems.push(PngError::InvalidSignature { found: inspection.signature.to_vec() });
+  }
+
+  for raw in &inspection.chunks {
+    if !raw.crc_ok() {
+      problems.push(PngError::CrcMismatch { index: raw.index, offset: raw.offset, expected: raw.stored_crc, actual: raw.computed_crc });
+    }
+    // the reserved bit is checked by validate
+    if raw.to_chunk().is_none() {
+      problems.push(PngError::InvalidChunkType { bytes: raw.chunk_type.bytes().to_vec(), index: Some(raw.index), offset: Some(raw.offset) });
+    }
+  }
+  if let Some(err) = inspection.error {
+    problems.push(err);
+  }
+
+  // unchecked types are kept, so the indices in the errors match the file
+  let chunks: Vec<Chunk> = inspection.chunks.iter()
+    .map(|raw| Chunk::from_checked_parts(raw.chunk_type.clone(), raw.data.to_vec(), raw.stored_crc))
+    .collect();
+  problems.extend(validate(&chunks));
+
+  let Some(ihdr) = header else { return problems };
+  for chunk in &chunks {
+    let result = match &chunk.chunk_type().bytes() {
+      // PLTE in a grayscale image is already reported by validate
+      b"PLTE" if matches!(ihdr.color_type, ColorType::Grayscale | ColorType::GrayscaleAlpha) => Ok(()),
+      b"PLTE" => Palette::try_from(chunk).and_then(|palette| palette.validate(&ihdr)),
+      b"tRNS" => Transparency::from_chunk(chunk, &ihdr)
+        .and_then(|transparency| transparency.validate(&ihdr, palette.as_ref())),
+      _ => Ancillary::from_chunk(chunk, &ihdr, palette.as_ref()).map(|_| ()),
+    };
+    problems.extend(result.err());
+  }
+
+  let idats = chunks.iter().enumerate()
+    .filter(|(_, c)| c.chunk_type().bytes() == *b"IDAT")
+    .map(|(index, c)| (index, c.data()));
+  match decoder::expected_data_len(&ihdr) {
+    None => problems.push(PngError::InvalidImageData { reason: format!("image of {}x{} is too large", ihdr.width, ihdr.height) }),
+    Some(expected) => match zlib::inflated_len(idats, expected) {
+      Ok(len) if len > expected => problems.push(PngError::InvalidImageData {
+        reason: format!("image data inflates to more than the {} bytes the header asks for", expected),
+      }),
+      Ok(len) if len < expected => problems.push(PngError::InvalidImageData {
+        reason: format!("image data inflates to {} bytes, the header asks for {}", len, expected),
+      }),
+      Ok(_) => {},
+      // a missing IDAT is already reported by validate
+      Err(PngError::ChunkNotFound { .. }) => {},
+      Err(err) => problems.push(err),
+    },
+  }
+  problems
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::chunk_type::ChunkType;
+  use crate::png::{tests::PNG_FILE, Png};
+  use std::str::FromStr;
+
+  fn chunk(chunk_type: &str) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![])
+  }
+
+  #[test]
+  fn test_valid_file() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    assert!(validate(png.chunks()).is_empty());
+  }
+
+  #[test]
+  fn test_every_violation_is_reported() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    // IHDR sRGB gAMA pHYs IDAT RuSt IEND
+    let mut chunks = png.chunks().to_vec();
+    chunks.insert(5, chunk("IDAT"));
+    chunks.insert(5, chunk("tEXt"));
+    chunks.push(chunk("tEXt"));
+    chunks.push(chunk("gAMA"));
+    chunks.insert(1, Chunk::new(ChunkType::from_str("PLTE").unwrap(), vec![0; 3]));
+    let kinds: Vec<String> = validate(&chunks).iter().map(|err| err.to_string()).collect();
+    assert_eq!(kinds, vec![
+      "IEND at chunk 9 must be the last chunk",
+      "gAMA at chunk 11 may appear only once, found 2",
+      "tEXt at chunk 6 is not allowed between IDAT chunks",
+      "gAMA at chunk 11 must come before the first IDAT",
+      "sRGB at chunk 2 must come before PLTE",
+      "gAMA at chunk 3 must come before PLTE",
+      "gAMA at chunk 11 must come before PLTE",
+    ]);
+
+    let mut chunks = png.chunks().to_vec();
+    chunks.insert(5, chunk("Rust"));
+    let kinds: Vec<String> = validate(&chunks).iter().map(|err| err.to_string()).collect();
+    assert_eq!(kinds, vec!["Rust at chunk 5 has the reserved bit set, the third letter must be uppercase"]);
+  }
+
+  #[test]
+  fn test_check_bytes() {
+    assert!(check_bytes(&PNG_FILE).is_empty());
+
+    let mut bytes = PNG_FILE.to_vec();
+    // crc of IHDR, then the reserved bit of RuSt
+    bytes[29] ^= 1;
+    let rust = bytes.windows(4).position(|w| w == b"RuSt").unwrap();
+    bytes[rust + 2] = b's';
+    let kinds: Vec<&str> = check_bytes(&bytes).iter().map(|err| err.kind()).collect();
+    assert_eq!(kinds, vec!["crc_mismatch", "crc_mismatch", "structure_violation"]);
+
+    // cut in the middle of IDAT
+    let kinds: Vec<&str> = check_bytes(&PNG_FILE[..2000]).iter().map(|err| err.kind()).collect();
+    assert_eq!(kinds, vec!["truncated_chunk", "chunk_not_found", "chunk_not_found"]);
+  }
+
+  #[test]
+  fn test_check_bytes_image_size() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    let mut chunks = png.chunks().to_vec();
+    let image_data = zlib::inflate_chunks([(4, chunks[4].data())]).unwrap();
+    chunks[4] = Chunk::new(chunks[4].chunk_type().clone(), zlib::deflate(&image_data[1..], 6));
+    let problems = check_bytes(&Png::from_chunks(chunks.clone()).as_bytes());
+    assert_eq!(problems[0].to_string(), "invalid image data: image data inflates to 10049 bytes, the header asks for 10050");
+
+    chunks[4] = Chunk::new(chunks[4].chunk_type().clone(), zlib::deflate(&vec![0; 1_000_000], 9));
+    let problems = check_bytes(&Png::from_chunks(chunks).as_bytes());
+    assert_eq!(problems[0].to_string(), "invalid image data: image data inflates to more than the 10050 bytes the header asks for");
+  }
+
+  #[test]
+  fn test_check_bytes_never_panics() {
+    for i in 0..PNG_FILE.len() {
+      let mut bytes = PNG_FILE.to_vec();
+      bytes[i] ^= 0xa5;
+      check_bytes(&bytes);
+      check_bytes(&PNG_FILE[..i]);
+    }
+  }
+
+  #[test]
+  fn test_missing_chunks() {
+    let violations = validate(&[chunk("tEXt"), Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![1])]);
+    let kinds: Vec<&str> = violations.iter().map(|err| err.kind()).collect();
+    assert_eq!(kinds, vec!["chunk_not_found", "invalid_chunk_data", "chunk_not_found"]);
+  }
+}
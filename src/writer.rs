@@ -0,0 +1,143 @@
+use std::io::Write;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{PngError, Result};
+
+/// Writes a png to any `Write` one chunk at a time. The signature is written
+/// on creation and every chunk goes straight to the sink. The writer refuses
+/// anything but IHDR as the first chunk and anything after IEND, `finish`
+/// checks that IEND was written.
+pub struct PngWriter<W: Write> {
+  writer: W,
+  index: usize,
+  offset: usize,
+  ended: bool,
+}
+
+impl<W: Write> PngWriter<W> {
+  /// Creates a writer and writes the png signature.
+  pub fn new(mut writer: W) -> Result<PngWriter<W>> {
+    writer.write_all(&Png::STANDARD_HEADER)?;
+    Ok(PngWriter {
+      writer,
+      index: 0,
+      offset: 8,
+      ended: false,
+    })
+  }
+
+  /// Returns the offset in the file of the next chunk to write.
+  pub fn offset(&self) -> usize {
+    self.offset
+  }
+
+  /// Writes a complete chunk, using the crc it already has.
+  pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+    self.check_order(chunk.chunk_type())?;
+    chunk.write_to(&mut self.writer)?;
+    self.advance(chunk.chunk_type(), chunk.data().len());
+    Ok(())
+  }
+
+  /// Writes a chunk from its type and data, the crc is computed on the fly
+  /// so no `Chunk` has to be built.
+  pub fn write_chunk_data(&mut self, chunk_type: &ChunkType, data: &[u8]) -> Result<()> {
+    self.check_order(chunk_type)?;
+    if data.len() > Chunk::MAX_LENGTH as usize {
+      let length = u32::try_from(data.len()).unwrap_or(u32::MAX);
+      return Err(PngError::ChunkTooLong { index: self.index, offset: self.offset, length });
+    }
+    let length = data.len() as u32;
+
+    self.writer.write_all(&length.to_be_bytes())?;
+    self.writer.write_all(&chunk_type.bytes())?;
+    self.writer.write_all(data)?;
+    self.writer.write_all(&Chunk::compute_crc(chunk_type, data).to_be_bytes())?;
+    self.advance(chunk_type, data.len());
+    Ok(())
+  }
+
+  /// Checks that IEND was written, flushes and returns the underlying writer.
+  pub fn finish(mut self) -> Result<W> {
+    if !self.ended {
+      return Err(PngError::InvalidChunkOrder {
+        index: self.index,
+        chunk_type: "IEND".to_string(),
+        reason: "the last chunk must be IEND".to_string(),
+      });
+    }
+    self.writer.flush()?;
+    Ok(self.writer)
+  }
+
+  fn check_order(&self, chunk_type: &ChunkType) -> Result<()> {
+    let reason = if self.ended {
+      "no chunks are allowed after IEND"
+    } else if self.index == 0 && chunk_type.bytes() != *b"IHDR" {
+      "the first chunk must be IHDR"
+    } else {
+      return Ok(());
+    };
+
+    Err(PngError::InvalidChunkOrder {
+      index: self.index,
+      chunk_type: chunk_type.to_string(),
+      reason: reason.to_string(),
+    })
+  }
+
+  fn advance(&mut self, chunk_type: &ChunkType, length: usize) {
+    self.index += 1;
+    self.offset += 12 + length;
+    self.ended = chunk_type.bytes() == *b"IEND";
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::png::tests::PNG_FILE;
+  use crate::reader::PngReader;
+  use std::str::FromStr;
+
+  #[test]
+  fn test_copy_png() {
+    let mut writer = PngWriter::new(Vec::new()).unwrap();
+    for chunk in PngReader::new(&PNG_FILE[..]).unwrap() {
+      writer.write_chunk(&chunk.unwrap()).unwrap();
+    }
+    assert_eq!(writer.finish().unwrap(), PNG_FILE.to_vec());
+  }
+
+  #[test]
+  fn test_write_chunk_data() {
+    let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![1, 2, 3]);
+    let mut writer = PngWriter::new(Vec::new()).unwrap();
+    writer.write_chunk_data(chunk.chunk_type(), chunk.data()).unwrap();
+    writer.write_chunk_data(&ChunkType::from_str("IEND").unwrap(), &[]).unwrap();
+    let bytes = writer.finish().unwrap();
+    assert_eq!(&bytes[8..8 + 15], chunk.as_bytes().as_slice());
+  }
+
+  #[test]
+  fn test_ihdr_must_be_first() {
+    let mut writer = PngWriter::new(Vec::new()).unwrap();
+    let result = writer.write_chunk_data(&ChunkType::from_str("RuSt").unwrap(), b"data");
+    assert!(matches!(result, Err(PngError::InvalidChunkOrder { index: 0, .. })));
+  }
+
+  #[test]
+  fn test_iend_must_be_last() {
+    let mut writer = PngWriter::new(Vec::new()).unwrap();
+    writer.write_chunk_data(&ChunkType::from_str("IHDR").unwrap(), &[]).unwrap();
+    assert!(writer.finish().is_err());
+
+    let mut writer = PngWriter::new(Vec::new()).unwrap();
+    writer.write_chunk_data(&ChunkType::from_str("IHDR").unwrap(), &[]).unwrap();
+    writer.write_chunk_data(&ChunkType::from_str("IEND").unwrap(), &[]).unwrap();
+    let result = writer.write_chunk_data(&ChunkType::from_str("RuSt").unwrap(), b"data");
+    assert!(matches!(result, Err(PngError::InvalidChunkOrder { index: 2, .. })));
+  }
+}
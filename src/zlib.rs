@@ -0,0 +1,169 @@
+use std::io::Write;
+
+use flate2::{write::ZlibEncoder, Compression, Decompress, FlushDecompress, Status};
+
+use crate::{PngError, Result};
+
+/// Inflates a zlib stream that is split over several chunks, e.g. the IDAT
+/// chunks of a png, without joining them first. Every item is the index of
+/// the chunk in the png and its data. The Adler-32 checksum at the end of the
+/// stream is verified. Errors name the chunk that was being read and the
+/// offset in the compressed stream. See `inflate_chunks_limited` for data
+/// that doesn't come from a trusted source.
+pub fn inflate_chunks<'a, I>(chunks: I) -> Result<Vec<u8>>
+where
+  I: IntoIterator<Item = (usize, &'a [u8])>,
+{
+  inflate_chunks_limited(chunks, usize::MAX)
+}
+
+/// Like `inflate_chunks` but fails with `InflateLimit` as soon as there are
+/// more than `limit` inflated bytes, so a small stream can't fill memory.
+pub fn inflate_chunks_limited<'a, I>(chunks: I, limit: usize) -> Result<Vec<u8>>
+where
+  I: IntoIterator<Item = (usize, &'a [u8])>,
+{
+  let mut out = Vec::new();
+  let mut over = false;
+  inflate_with(chunks, |data| {
+    over = data.len() > limit - out.len();
+    if !over {
+      out.extend_from_slice(data);
+    }
+    !over
+  })?;
+  if over {
+    return Err(PngError::InflateLimit { limit });
+  }
+  Ok(out)
+}
+
+/// Like `inflate_chunks` but only counts the inflated bytes, nothing is
+/// kept in memory. Stops as soon as there are more than `limit` bytes, the
+/// count is then `limit + 1`, so streams that inflate to huge sizes can be
+/// checked safely.
+pub fn inflated_len<'a, I>(chunks: I, limit: usize) -> Result<usize>
+where
+  I: IntoIterator<Item = (usize, &'a [u8])>,
+{
+  let mut len = 0usize;
+  inflate_with(chunks, |data| {
+    len = len.saturating_add(data.len());
+    len <= limit
+  })?;
+  Ok(len.min(limit.saturating_add(1)))
+}
+
+/// Inflates the chunks and hands the output to `sink` piece by piece, until
+/// the stream ends or `sink` returns false.
+fn inflate_with<'a, I, F>(chunks: I, mut sink: F) -> Result<()>
+where
+  I: IntoIterator<Item = (usize, &'a [u8])>,
+  F: FnMut(&[u8]) -> bool,
+{
+  let mut inflater = Decompress::new(true);
+  let mut buffer = vec![0; 32 * 1024];
+  let mut last_index = None;
+
+  for (index, data) in chunks {
+    last_index = Some(index);
+    let mut input = data;
+    while !input.is_empty() {
+      let (before_in, before_out) = (inflater.total_in(), inflater.total_out());
+      let status = inflater.decompress(input, &mut buffer, FlushDecompress::None)
+        .map_err(|err| zlib_error(index, &inflater, err.to_string()))?;
+      let consumed = (inflater.total_in() - before_in) as usize;
+      let produced = (inflater.total_out() - before_out) as usize;
+      if !sink(&buffer[..produced]) || status == Status::StreamEnd {
+        return Ok(());
+      }
+      if consumed == 0 && produced == 0 {
+        return Err(zlib_error(index, &inflater, "no progress inflating the stream".to_string()));
+      }
+      input = &input[consumed..];
+    }
+  }
+
+  let index = last_index.ok_or_else(|| PngError::ChunkNotFound { chunk_type: "IDAT".to_string() })?;
+  // all input is consumed, flush what is still buffered
+  loop {
+    let before = inflater.total_out();
+    let status = inflater.decompress(&[], &mut buffer, FlushDecompress::Finish)
+      .map_err(|err| zlib_error(index, &inflater, err.to_string()))?;
+    let produced = (inflater.total_out() - before) as usize;
+    if !sink(&buffer[..produced]) || status == Status::StreamEnd {
+      return Ok(());
+    }
+    if produced == 0 {
+      return Err(zlib_error(index, &inflater, "stream ends before the end of the compressed data".to_string()));
+    }
+  }
+}
+
+/// Compresses data into a zlib stream, `level` goes from 0 (none) to 9 (best).
+pub fn deflate(data: &[u8], level: u32) -> Vec<u8> {
+  let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level.min(9)));
+  // writing to a vec can't fail
+  encoder.write_all(data).unwrap();
+  encoder.finish().unwrap()
+}
+
+fn zlib_error(index: usize, inflater: &Decompress, reason: String) -> PngError {
+  PngError::Zlib { index, stream_offset: inflater.total_in() as usize, reason }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn compress(data: &[u8]) -> Vec<u8> {
+    deflate(data, 6)
+  }
+
+  #[test]
+  fn test_inflate_split_stream() {
+    let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let compressed = compress(&data);
+    let chunks: Vec<(usize, &[u8])> = compressed.chunks(7).enumerate().collect();
+    assert_eq!(inflate_chunks(chunks).unwrap(), data);
+  }
+
+  #[test]
+  fn test_adler_mismatch() {
+    let mut compressed = compress(b"some scanlines");
+    let last = compressed.len() - 1;
+    compressed[last] ^= 1;
+    let result = inflate_chunks(vec![(3, &compressed[..4]), (4, &compressed[4..])]);
+    assert!(matches!(result, Err(PngError::Zlib { index: 4, .. })));
+  }
+
+  #[test]
+  fn test_truncated_stream() {
+    let compressed = compress(b"some scanlines");
+    let result = inflate_chunks(vec![(2, &compressed[..compressed.len() - 6])]);
+    assert!(matches!(result, Err(PngError::Zlib { index: 2, .. })));
+  }
+
+  #[test]
+  fn test_inflated_len() {
+    let compressed = compress(&vec![0; 1_000_000]);
+    let chunks = || compressed.chunks(100).enumerate();
+    assert_eq!(inflated_len(chunks(), usize::MAX).unwrap(), 1_000_000);
+    assert_eq!(inflated_len(chunks(), 1_000_000).unwrap(), 1_000_000);
+    assert_eq!(inflated_len(chunks(), 1000).unwrap(), 1001);
+    assert!(inflated_len(vec![(0, &compressed[..50])], usize::MAX).is_err());
+  }
+
+  #[test]
+  fn test_inflate_limited() {
+    let compressed = compress(&vec![0; 1_000_000]);
+    let chunks = || compressed.chunks(100).enumerate();
+    assert_eq!(inflate_chunks_limited(chunks(), 1_000_000).unwrap().len(), 1_000_000);
+    assert!(matches!(inflate_chunks_limited(chunks(), 999_999), Err(PngError::InflateLimit { limit: 999_999 })));
+  }
+
+  #[test]
+  fn test_no_chunks() {
+    assert!(matches!(inflate_chunks(vec![]), Err(PngError::ChunkNotFound { .. })));
+  }
+}
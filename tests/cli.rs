@@ -0,0 +1,267 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use pngproject::ihdr::ColorType;
+use pngproject::image::ImageBuffer;
+use pngproject::png::Png;
+use serde_json::Value;
+
+fn testing_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("pngproject-cli-{}-{}", name, std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+/// A small valid png in `dir`.
+fn testing_png(dir: &Path) -> PathBuf {
+  let image = ImageBuffer::new(2, 2, ColorType::Grayscale, 8, vec![0, 64, 128, 255]).unwrap();
+  let file = dir.join("in.png");
+  fs::write(&file, Png::from_image(&image).unwrap().as_bytes()).unwrap();
+  file
+}
+
+fn run(args: &[&str]) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_pngproject")).args(args).output().unwrap()
+}
+
+fn stdout_json(output: &Output) -> Value {
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn stderr_json(output: &Output) -> Value {
+  assert!(!output.status.success());
+  assert!(output.stdout.is_empty());
+  serde_json::from_slice(&output.stderr).unwrap()
+}
+
+fn path(path: &Path) -> &str {
+  path.to_str().unwrap()
+}
+
+#[test]
+fn test_encode_decode_json() {
+  let dir = testing_dir("json");
+  let file = testing_png(&dir);
+  let out = dir.join("out.png");
+
+  let encoded = stdout_json(&run(&["--format", "json", "encode", path(&file), "ruSt", "hello", path(&out)]));
+  assert_eq!(encoded["command"], "encode");
+  assert_eq!(encoded["output"], path(&out));
+  assert_eq!(encoded["backup"], Value::Null);
+  assert_eq!(encoded["added"]["chunk"]["type"], "ruSt");
+  assert_eq!(encoded["added"]["chunk"]["length"], 5);
+  assert!(encoded["added"]["chunk"]["crc"].is_u64());
+  assert!(encoded["added"]["index"].is_u64());
+
+  let decoded = stdout_json(&run(&["--format", "json", "decode", path(&out), "ruSt"]));
+  assert_eq!(decoded["command"], "decode");
+  assert_eq!(decoded["found"]["chunk"], encoded["added"]["chunk"]);
+  assert_eq!(decoded["encoding"], "utf8");
+  assert_eq!(decoded["message"], "hello");
+
+  let validated = stdout_json(&run(&["--format", "json", "validate", path(&out)]));
+  assert_eq!(validated["valid"], true);
+  assert_eq!(validated["files"][0]["problems"], Value::Array(vec![]));
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_errors_json() {
+  let dir = testing_dir("errors");
+  let file = testing_png(&dir);
+
+  let missing = run(&["--format", "json", "decode", path(&file), "ruSt"]);
+  assert_eq!(missing.status.code(), Some(1));
+  let error = &stderr_json(&missing)["error"];
+  assert_eq!(error["kind"], "chunk_not_found");
+  assert!(error["message"].as_str().unwrap().contains("ruSt"));
+
+  // found wrong while running the command
+  let no_output = run(&["--format", "json", "encode", path(&file), "ruSt", "hello"]);
+  assert_eq!(no_output.status.code(), Some(1));
+  assert_eq!(stderr_json(&no_output)["error"]["kind"], "invalid_input");
+
+  // found wrong while parsing the arguments
+  let extra = run(&["--format=json", "encode", path(&file), "ruSt", "hello", "a", "b"]);
+  assert_eq!(extra.status.code(), Some(2));
+  let error = &stderr_json(&extra)["error"];
+  assert_eq!(error["kind"], "invalid_input");
+  assert!(!error["message"].as_str().unwrap().contains('\n'));
+
+  // text stays text
+  let text = run(&["decode", path(&file), "ruSt"]);
+  assert!(String::from_utf8(text.stderr).unwrap().starts_with("error: "));
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_closed_stdout() {
+  let dir = testing_dir("pipe");
+  let file = testing_png(&dir);
+  let out = dir.join("out.png");
+  let message = dir.join("message");
+  fs::write(&message, vec![b'x'; 1 << 20]).unwrap();
+  assert!(run(&["encode", path(&file), "ruSt", path(&out), "--input-file", path(&message)]).status.success());
+
+  // like `| head -c 0`, the reader is gone before anything is written
+  for encoding in ["--base64", "--raw"] {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pngproject"))
+      .args(["decode", path(&out), "ruSt", encoding])
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()
+      .unwrap();
+    drop(child.stdout.take());
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  }
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_keyword() {
+  let dir = testing_dir("keyword");
+  let file = testing_png(&dir);
+  let out = dir.join("out.png");
+
+  let encoded = stdout_json(&run(&["--format", "json", "encode", path(&file), "--keyword", "Title", "--compress",
+    "--language", "de", "Grüße", path(&out)]));
+  assert_eq!(encoded["added"]["chunk"]["type"], "iTXt");
+  assert_eq!(encoded["added"]["text"]["keyword"], "Title");
+
+  let decoded = stdout_json(&run(&["--format", "json", "decode", path(&out), "--keyword", "Title"]));
+  assert_eq!(decoded["message"], "Grüße");
+  assert_eq!(decoded["found"]["text"]["compressed"], true);
+  assert_eq!(decoded["found"]["text"]["language"], "de");
+  let decoded = run(&["decode", path(&out), "--keyword", "Title"]);
+  assert_eq!(String::from_utf8(decoded.stdout).unwrap(), "Grüße\n");
+
+  let missing = run(&["--format", "json", "decode", path(&out), "--keyword", "Author"]);
+  assert_eq!(stderr_json(&missing)["error"]["kind"], "keyword_not_found");
+
+  let removed = run(&["remove", path(&out), "--keyword", "Title", "--in-place"]);
+  assert!(removed.status.success());
+  assert!(String::from_utf8(removed.stdout).unwrap().starts_with("removed 1 text with keyword \"Title\""));
+  let gone = run(&["--format", "json", "decode", path(&out), "--keyword", "Title"]);
+  assert_eq!(stderr_json(&gone)["error"]["kind"], "keyword_not_found");
+  let again = run(&["--format", "json", "remove", path(&out), "--keyword", "Title", "--in-place"]);
+  assert_eq!(stderr_json(&again)["error"]["kind"], "keyword_not_found");
+  assert_eq!(fs::read(&out).unwrap(), fs::read(&file).unwrap());
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_encode_needs_a_message() {
+  let dir = testing_dir("no-message");
+  let file = testing_png(&dir);
+  let original = fs::read(&file).unwrap();
+
+  for args in [&["ruSt", "--in-place"][..], &["--keyword", "Title", "--in-place"]] {
+    let output = run(&[&["--format", "json", "encode", path(&file)][..], args].concat());
+    assert_eq!(output.status.code(), Some(1));
+    let error = &stderr_json(&output)["error"];
+    assert_eq!(error["kind"], "invalid_input");
+    assert!(error["message"].as_str().unwrap().contains("no MESSAGE"));
+    assert_eq!(fs::read(&file).unwrap(), original);
+  }
+
+  // an empty message is fine when it is given
+  assert!(run(&["encode", path(&file), "ruSt", "", "--in-place"]).status.success());
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_split_payload_next_to_a_plain_chunk() {
+  let dir = testing_dir("split");
+  let file = testing_png(&dir);
+  let out = dir.join("out.png");
+  assert!(run(&["encode", path(&file), "ruSt", "plain", path(&out)]).status.success());
+  let payload = "x".repeat(100);
+  assert!(run(&["encode", path(&out), "ruSt", &payload, "--in-place", "--max-chunk-size", "40"]).status.success());
+
+  let decoded = stdout_json(&run(&["--format", "json", "decode", path(&out), "ruSt"]));
+  assert_eq!(decoded["message"], payload.as_str());
+  assert_eq!(decoded["found"]["ignored"].as_array().unwrap().len(), 1);
+  assert_eq!(decoded["found"]["ignored"][0]["length"], 5);
+
+  let decoded = run(&["decode", path(&out), "ruSt"]);
+  assert_eq!(String::from_utf8(decoded.stdout).unwrap(), format!("{}\n", payload));
+  assert!(String::from_utf8(decoded.stderr).unwrap().contains("ignored 1 ruSt chunk"));
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_validate_broken_files() {
+  let dir = testing_dir("validate");
+  let file = testing_png(&dir);
+  let mut bytes = fs::read(&file).unwrap();
+  let bad_crc = dir.join("bad-crc.png");
+  *bytes.last_mut().unwrap() ^= 1;
+  fs::write(&bad_crc, &bytes).unwrap();
+  let truncated = dir.join("truncated.png");
+  fs::write(&truncated, &bytes[..bytes.len() - 6]).unwrap();
+  let missing = dir.join("missing.png");
+
+  let output = run(&["validate", path(&file), path(&bad_crc), path(&truncated), path(&missing)]);
+  assert_eq!(output.status.code(), Some(1));
+  let report = String::from_utf8(output.stdout).unwrap();
+  let lines: Vec<&str> = report.lines().collect();
+  assert_eq!(lines[0], format!("{}: ok", path(&file)));
+  assert_eq!(lines[1], format!("{}: 1 problem", path(&bad_crc)));
+  assert!(lines[2].starts_with("  crc mismatch in chunk"));
+  assert!(report.contains(&format!("{}: ", path(&truncated))));
+  assert!(report.contains(&format!("{}: 1 problem\n  io error", path(&missing))));
+
+  let output = run(&["--format", "json", "validate", path(&file), path(&bad_crc), path(&truncated), path(&missing)]);
+  assert_eq!(output.status.code(), Some(1));
+  let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+  assert_eq!(report["valid"], false);
+  let kinds: Vec<&Value> = report["files"].as_array().unwrap().iter().map(|file| &file["problems"][0]["kind"]).collect();
+  assert_eq!(kinds, [&Value::Null, &Value::from("crc_mismatch"), &Value::from("truncated_chunk"), &Value::from("io")]);
+  assert_eq!(report["files"][0]["valid"], true);
+  assert_eq!(report["files"][3]["valid"], false);
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_update() {
+  let dir = testing_dir("update");
+  let file = testing_png(&dir);
+  let out = dir.join("out.png");
+  assert!(run(&["encode", path(&file), "ruSt", "first", path(&out), "--position", "after-ihdr"]).status.success());
+  assert!(run(&["encode", path(&out), "ruSt", "second", "--in-place"]).status.success());
+  let chunk_types = |file: &Path| -> Vec<String> {
+    let printed = stdout_json(&run(&["--format", "json", "print", path(file)]));
+    printed["chunks"].as_array().unwrap().iter().map(|chunk| chunk["type"].as_str().unwrap().to_string()).collect()
+  };
+  let before = chunk_types(&out);
+
+  let updated_file = dir.join("updated.png");
+  let updated = stdout_json(&run(&["--format", "json", "update", path(&out), "ruSt", "changed", "--index", "1",
+    "--output", path(&updated_file)]));
+  assert_eq!(updated["index"], 1);
+  assert_eq!(updated["old"]["length"], 6);
+  assert_eq!(updated["new"]["length"], 7);
+  assert_eq!(chunk_types(&updated_file), before);
+  let png = Png::try_from(fs::read(&updated_file).unwrap().as_slice()).unwrap();
+  let texts: Vec<String> = png.chunks_by_type("ruSt").iter().map(|c| c.data_as_string().unwrap()).collect();
+  assert_eq!(texts, ["first", "changed"]);
+  // --output leaves the input alone
+  assert_eq!(Png::try_from(fs::read(&out).unwrap().as_slice()).unwrap().chunks_by_type("ruSt")[1].data(), b"second");
+
+  assert!(run(&["update", path(&out), "ruSt", "again", "--in-place"]).status.success());
+  let png = Png::try_from(fs::read(&out).unwrap().as_slice()).unwrap();
+  assert_eq!(png.chunks_by_type("ruSt")[0].data(), b"again");
+  assert_eq!(chunk_types(&out), before);
+
+  let out_of_range = run(&["--format", "json", "update", path(&out), "ruSt", "x", "--index", "2", "--in-place"]);
+  let error = &stderr_json(&out_of_range)["error"];
+  assert_eq!(error["kind"], "chunk_index_out_of_range");
+  assert_eq!(error["message"], "no ruSt chunk at index 2, there are only 2");
+  let missing = run(&["--format", "json", "update", path(&out), "teSt", "x", "--in-place"]);
+  assert_eq!(stderr_json(&missing)["error"]["kind"], "chunk_not_found");
+  fs::remove_dir_all(&dir).unwrap();
+}